@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT
+
+//! A blocking interface for applications without an async runtime.
+
+use crate::{
+    runtime, sender::Statistics, Connectivity, ConnectivityMonitorBuilder, ConnectivityState,
+    Error, MonitorHealth,
+};
+use alloc::sync::Arc;
+use futures::{channel::oneshot, future, select_biased, FutureExt, StreamExt};
+use log::{debug, warn};
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+};
+use tokio::sync::watch;
+
+/// Runs a driver on a background thread and receives its connectivity updates without an async runtime.
+///
+/// Dropping it stops the driver and joins the background thread.
+#[derive(Debug)]
+pub struct Monitor {
+    /// The receive end of the channel connectivity updates are forwarded to
+    receiver: Receiver<Connectivity>,
+    /// Holds the last forwarded connectivity
+    current: watch::Receiver<Connectivity>,
+    /// The counters the driver records
+    statistics: Arc<Statistics>,
+    /// Stops the driver when it is dropped
+    stop: Option<oneshot::Sender<()>>,
+    /// The background thread running the driver
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Monitor {
+    /// Spawns a background thread that runs the driver built by builder and forwards its updates, calling callback for every update
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the thread couldn't be spawned or the driver couldn't be built.
+    pub(crate) fn spawn<F>(
+        builder: ConnectivityMonitorBuilder,
+        mut callback: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(Connectivity) + Send + 'static,
+    {
+        let (tx, receiver) = mpsc::channel();
+        let (current_tx, current) = watch::channel(Connectivity {
+            ipv4: ConnectivityState::None,
+            ipv6: ConnectivityState::None,
+        });
+        let (stop, stopped) = oneshot::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+        let statistics = Arc::<Statistics>::default();
+        let driver_statistics = Arc::clone(&statistics);
+
+        let work = async move {
+            // the driver is built on the background thread, so it runs within the context of its runtime
+            let (driver, mut stream) = match builder.stream(driver_statistics) {
+                Ok(built) => {
+                    drop(ready_tx.send(Ok(())));
+                    built
+                }
+                Err(error) => {
+                    drop(ready_tx.send(Err(error)));
+                    return Ok(());
+                }
+            };
+            let forward = async move {
+                while let Some(connectivity) = stream.next().await {
+                    current_tx.send_replace(connectivity);
+                    callback(connectivity);
+                    if tx.send(connectivity).is_err() {
+                        break;
+                    }
+                }
+            };
+            select_biased! {
+                _ = stopped.fuse() => {
+                    debug!("blocking monitor stopped");
+                    Ok(())
+                },
+                (result, ()) = future::join(driver, forward).fuse() => result,
+            }
+        };
+
+        debug!("spawning blocking monitor thread");
+        let thread = thread::Builder::new()
+            .name("network-connectivity".to_owned())
+            .spawn(move || runtime::block_on(work).map_err(Error::Runtime)?)
+            .map_err(Error::Runtime)?;
+
+        let mut monitor = Self {
+            receiver,
+            current,
+            statistics,
+            stop: Some(stop),
+            thread: Some(thread),
+        };
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(monitor),
+            Ok(Err(error)) => {
+                monitor.join()?;
+                Err(error)
+            }
+            // the thread exited before the driver was built
+            Err(_) => {
+                monitor.join()?;
+                Err(Error::Panicked)
+            }
+        }
+    }
+
+    /// Get the receive end of the channel through which every connectivity update is received.
+    ///
+    /// Updates are queued until they are received.
+    #[must_use]
+    pub const fn receiver(&self) -> &Receiver<Connectivity> {
+        &self.receiver
+    }
+
+    /// Get the last received connectivity, which has no connectivity for both ip types until the driver emitted the initial connectivity
+    #[must_use]
+    pub fn current(&self) -> Connectivity {
+        *self.current.borrow()
+    }
+
+    /// Get the number of times the driver resynchronised its state with the system
+    ///
+    /// See [`ConnectivityMonitor::resyncs`](crate::ConnectivityMonitor::resyncs) for details.
+    #[must_use]
+    pub fn resyncs(&self) -> u64 {
+        self.statistics.resyncs()
+    }
+
+    /// Get the number of times the driver restarted after it failed
+    ///
+    /// See [`ConnectivityMonitor::restarts`](crate::ConnectivityMonitor::restarts) for details.
+    #[must_use]
+    pub fn restarts(&self) -> u64 {
+        self.statistics.restarts()
+    }
+
+    /// Get the health of the driver
+    #[must_use]
+    pub fn health(&self) -> MonitorHealth {
+        self.statistics.health()
+    }
+
+    /// Stops the driver and waits for the background thread to finish.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver failed or the background thread panicked.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    /// Stops the driver and joins the background thread when this wasn't done yet
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver failed or the background thread panicked.
+    fn join(&mut self) -> Result<(), Error> {
+        drop(self.stop.take());
+        self.thread.take().map_or(Ok(()), |thread| {
+            thread
+                .join()
+                .unwrap_or_else(|_payload| Err(Error::Panicked))
+        })
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        if let Err(error) = self.join() {
+            warn!("blocking monitor failed {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConnectivityMonitor;
+    use core::time::Duration;
+
+    /// How long the initial connectivity may take to arrive
+    const INITIAL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn initial_connectivity_is_forwarded_and_stop_joins_the_thread() {
+        let (called_tx, called) = mpsc::channel();
+        let monitor = ConnectivityMonitor::builder()
+            .build_blocking_with_callback(move |connectivity| {
+                called_tx.send(connectivity).unwrap();
+            })
+            .unwrap();
+        let initial = monitor.receiver().recv_timeout(INITIAL).unwrap();
+        // the callback is called before the update is received
+        assert_eq!(called.try_recv().unwrap(), initial);
+        assert_eq!(monitor.current(), initial);
+        assert_eq!(monitor.health(), MonitorHealth::Running);
+
+        monitor.stop().unwrap();
+        // the callback is dropped with the background thread after the updates since the initial one
+        called.try_iter().for_each(drop);
+        assert!(matches!(
+            called.try_recv(),
+            Err(mpsc::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn dropping_stops_the_driver_and_joins_the_thread() {
+        let (called_tx, called) = mpsc::channel();
+        let monitor = ConnectivityMonitor::builder()
+            .build_blocking_with_callback(move |connectivity| {
+                called_tx.send(connectivity).unwrap();
+            })
+            .unwrap();
+        monitor.receiver().recv_timeout(INITIAL).unwrap();
+        drop(monitor);
+        assert!(called.try_iter().count() >= 1);
+        assert!(matches!(
+            called.try_recv(),
+            Err(mpsc::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn driver_that_fails_to_build_is_an_error() {
+        let spawned = ConnectivityMonitor::builder()
+            .namespace(crate::NetworkNamespace::Path("/proc/self/ns/net".into()))
+            .probe(crate::Probe::Dns {
+                host: "example.com".to_owned(),
+            })
+            .build_blocking();
+        assert!(matches!(spawned, Err(Error::Probe(_))));
+    }
+}
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: MIT
 
 //! This crate allows you to receive network connectivity updates through a channel.
+//!
+//! The async runtime the driver uses is selected with one of the features `runtime-tokio` (default), `runtime-async-std` or `runtime-smol`.
 
 #![warn(clippy::cargo, clippy::nursery, clippy::pedantic, clippy::restriction)]
 #![allow(
@@ -11,15 +13,43 @@
     clippy::single_char_lifetime_names
 )]
 
+extern crate alloc;
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub mod blocking;
 #[cfg(target_os = "linux")]
 mod linux;
-#[cfg(any(target_os = "linux"))]
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod probe;
+mod runtime;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod sender;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
 mod state;
 #[cfg(target_os = "windows")]
 mod windows;
 
-use futures::Future;
-use std::error::Error;
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    Future, FutureExt, Stream, StreamExt,
+};
+use probe::{Prober, Settings};
+use sender::{Backoff, Callback, Callbacks, Closed, Debounce, Emitter, Sender, Statistics};
+use state::Filter;
+use std::{
+    error, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Instant,
+};
+#[cfg(target_os = "linux")]
+use std::{os::unix::io::OwnedFd, path::PathBuf};
 
 /// Represents connectivity to the internet.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
@@ -27,12 +57,71 @@ use std::error::Error;
 pub enum ConnectivityState {
     /// No connectivity
     None,
-    /// Connectivity to the local network
+    /// Connectivity to the local link using only link-local addresses, like an interface that is plugged in but not configured
+    LinkLocal,
+    /// Connectivity to the local network using a routable address
     Network,
+    /// Connectivity to a captive portal that must be passed before the internet is reachable, only detected by a [`Probe::Http`]
+    CaptivePortal,
     /// Connectivity to the internet
     Internet,
 }
 
+/// Represents the operational state of an interface.
+///
+/// Only an interface that is [`LinkState::Up`] contributes to the connectivity.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum LinkState {
+    /// The interface is administratively down or not present
+    Down,
+    /// The interface is administratively up but the layer below it, like the carrier, is down
+    LowerLayerDown,
+    /// The interface waits for an external event, like a wireless interface that is authenticating
+    Dormant,
+    /// The interface is in a test mode
+    Testing,
+    /// The interface is able to communicate with the network
+    Up,
+}
+
+/// An active check that verifies the internet is reachable.
+///
+/// Every check is performed separately for ipv4 and ipv6.
+/// [`Probe::Http`] and [`Probe::Tcp`] only connect to addresses of that ip type, [`Probe::Dns`] only checks name resolution.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub enum Probe {
+    /// Sends an HTTP GET request to url and expects a response with status and, when it is set, a body containing body.
+    ///
+    /// A redirect or a response with status 200 that doesn't match is considered to come from a captive portal, any other response means the internet isn't reachable.
+    /// The url of the portal is taken from the `Location` header of the response, or is url itself when the response was not redirected.
+    /// Only `http://` urls are supported.
+    Http {
+        /// The url to request
+        url: String,
+        /// The expected status code of the response
+        status: u16,
+        /// The text the body of the response must contain
+        body: Option<String>,
+    },
+    /// Opens a TCP connection to port on host
+    Tcp {
+        /// The host name or address to connect to
+        host: String,
+        /// The port to connect to
+        port: u16,
+    },
+    /// Resolves host with the resolver of the system and expects an address of the ip type
+    ///
+    /// This is a name resolution check only: the resolver can answer from its cache and doesn't send its queries over the ip type that is checked.
+    /// An ip type is considered reachable when host has an address of that type, regardless of whether that address can be reached.
+    Dns {
+        /// The host name to resolve
+        host: String,
+    },
+}
+
 /// Represents connectivity to the internet separated by ipv4 and ipv6.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[non_exhaustive]
@@ -65,34 +154,1160 @@ impl Connectivity {
     }
 }
 
-/// Creates a driver that sends connectivity updates to a channel.
+/// Represents a default gateway reachable through an interface.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct Gateway<T> {
+    /// The address of the gateway, none for a default route without a gateway like on a point-to-point link
+    pub address: Option<T>,
+    /// The priority of the default route to the gateway, lower values are preferred
+    pub priority: u32,
+}
+
+/// Represents the duplicate address detection state of an address.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum AddressState {
+    /// The address is being verified to be unique on the link and can't be used yet
+    Tentative,
+    /// The address is being verified to be unique on the link with optimistic duplicate address detection, so it can already be used
+    Optimistic,
+    /// The address is already used by another host on the link and can't be used
+    Duplicate,
+    /// The preferred lifetime of the address expired, so it isn't used for new connections
+    Deprecated,
+    /// The address can be used
+    Preferred,
+}
+
+/// Represents the scope of an address, ordered from the smallest to the largest reach.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum AddressScope {
+    /// A loopback address that is only valid on this host
+    Host,
+    /// A link-local address in `169.254.0.0/16` or `fe80::/10` that is only valid on the link it is assigned to
+    LinkLocal,
+    /// A unique local ipv6 address in `fc00::/7`
+    UniqueLocal,
+    /// A private ipv4 address in `10.0.0.0/8`, `172.16.0.0/12` or `192.168.0.0/16`
+    Private,
+    /// Any other address
+    Global,
+}
+
+/// Represents an address of an interface.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct AddressSnapshot<T> {
+    /// The address
+    pub address: T,
+    /// The duplicate address detection state of the address
+    pub state: AddressState,
+    /// The scope of the address
+    pub scope: AddressScope,
+    /// When the preferred lifetime of the address expires, none when it never expires
+    pub preferred_until: Option<Instant>,
+    /// When the valid lifetime of the address expires, none when it never expires
+    pub valid_until: Option<Instant>,
+    /// Whether the address can be the source of traffic, only those addresses contribute to the connectivity
+    pub usable: bool,
+}
+
+/// Represents the state of a single ip type on an interface.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub struct IpSnapshot<T> {
+    /// The addresses of the interface ordered by address, including those that aren't usable
+    pub addresses: Vec<AddressSnapshot<T>>,
+    /// The default gateways of the interface ordered by priority
+    pub gateways: Vec<Gateway<T>>,
+    /// The connectivity state of the interface
+    ///
+    /// When a [`Probe`] is set, only the interface with the default route of the lowest priority can be [`ConnectivityState::Internet`] or [`ConnectivityState::CaptivePortal`], because the probe only reaches the internet through that interface.
+    /// Other interfaces with a default route are [`ConnectivityState::Network`].
+    pub state: ConnectivityState,
+    /// The url of the captive portal when state is [`ConnectivityState::CaptivePortal`]
+    pub portal: Option<String>,
+}
+
+/// Represents the state of a single interface.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub struct InterfaceSnapshot {
+    /// The index of the interface
+    pub index: u32,
+    /// The name of the interface if it is known
+    pub name: Option<String>,
+    /// The hardware address of the interface if it is known, like the MAC address of an ethernet interface
+    pub mac: Option<Vec<u8>>,
+    /// The maximum transmission unit of the interface if it is known
+    pub mtu: Option<u32>,
+    /// The kind of the interface, like `bridge` or `wireguard`, or none for physical devices and on windows
+    pub kind: Option<String>,
+    /// The operational state of the interface
+    pub link_state: LinkState,
+    /// The ipv4 state of the interface
+    pub ipv4: IpSnapshot<Ipv4Addr>,
+    /// The ipv6 state of the interface
+    pub ipv6: IpSnapshot<Ipv6Addr>,
+}
+
+impl InterfaceSnapshot {
+    /// Get the connectivity of this interface
+    #[allow(clippy::must_use_candidate)]
+    pub const fn connectivity(&self) -> Connectivity {
+        Connectivity {
+            ipv4: self.ipv4.state,
+            ipv6: self.ipv6.state,
+        }
+    }
+}
+
+/// Represents a change in the network configuration observed by a driver.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// An interface became [`LinkState::Up`]
+    LinkUp {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+    },
+    /// An interface stopped being [`LinkState::Up`] or was removed while it was up
+    LinkDown {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+    },
+    /// An interface was renamed
+    LinkRenamed {
+        /// The index of the interface
+        index: u32,
+        /// The name before the rename
+        old: String,
+        /// The name after the rename
+        new: String,
+    },
+    /// An address was added to an interface
+    AddressAdded {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+        /// The added address
+        address: IpAddr,
+    },
+    /// An address was removed from an interface
+    AddressRemoved {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+        /// The removed address
+        address: IpAddr,
+    },
+    /// A default route was added to an interface
+    DefaultRouteAdded {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+        /// Whether it is an ipv6 default route, otherwise it is an ipv4 default route
+        ipv6: bool,
+        /// The gateway of the default route, none when it has no gateway
+        gateway: Option<IpAddr>,
+        /// The priority of the default route
+        priority: u32,
+    },
+    /// A default route was removed from an interface
+    DefaultRouteRemoved {
+        /// The index of the interface
+        index: u32,
+        /// The name of the interface if it is known
+        name: Option<String>,
+        /// Whether it is an ipv6 default route, otherwise it is an ipv4 default route
+        ipv6: bool,
+        /// The gateway of the default route, none when it has no gateway
+        gateway: Option<IpAddr>,
+        /// The priority of the default route
+        priority: u32,
+    },
+    /// The aggregated connectivity changed
+    ConnectivityChanged {
+        /// The connectivity before the change
+        old: Connectivity,
+        /// The connectivity after the change
+        new: Connectivity,
+        /// The event that caused the change, this is [None] for the initial connectivity
+        cause: Option<Box<Self>>,
+    },
+}
+
+/// Represents the health of a driver.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub enum MonitorHealth {
+    /// The driver is running
+    #[default]
+    Running,
+    /// The driver failed and is restarting, see [`ConnectivityMonitorBuilder::supervise`]
+    Restarting,
+    /// The driver failed and stopped
+    Failed,
+}
+
+/// Represents the errors that can occur while monitoring the connectivity.
+#[allow(clippy::error_impl_error)]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The network namespace couldn't be entered
+    Namespace(io::Error),
+    /// The socket or subscription through which the system notifies changes couldn't be created or released
+    Socket(io::Error),
+    /// The socket couldn't join a multicast group
+    Membership {
+        /// The group that couldn't be joined
+        group: u32,
+        /// The error of the system
+        source: io::Error,
+    },
+    /// The state couldn't be retrieved from the system
+    Dump(Box<dyn error::Error + Send + Sync>),
+    /// The system answered with a netlink error code, which is contained as an os error
+    Netlink(io::Error),
+    /// The probe is invalid or couldn't be performed
+    Probe(Box<dyn error::Error + Send + Sync>),
+    /// The channel between the driver and its receivers is closed
+    Closed,
+    /// The async runtime or the background thread couldn't be created
+    Runtime(io::Error),
+    /// The background thread panicked
+    Panicked,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Namespace(_) => write!(f, "couldn't enter or leave the network namespace"),
+            Self::Socket(_) => write!(f, "couldn't create or release the socket"),
+            Self::Membership { group, .. } => write!(f, "couldn't join multicast group {group}"),
+            Self::Dump(_) => write!(f, "couldn't retrieve the state from the system"),
+            Self::Netlink(_) => write!(f, "received a netlink error"),
+            Self::Probe(_) => write!(f, "the probe failed"),
+            Self::Closed => write!(f, "the channel is closed"),
+            Self::Runtime(_) => write!(f, "couldn't create the runtime"),
+            Self::Panicked => write!(f, "the background thread panicked"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Namespace(ref source)
+            | Self::Socket(ref source)
+            | Self::Membership { ref source, .. }
+            | Self::Netlink(ref source)
+            | Self::Runtime(ref source) => Some(source),
+            Self::Dump(ref source) | Self::Probe(ref source) => Some(&**source),
+            Self::Closed | Self::Panicked => None,
+        }
+    }
+}
+
+/// The receive end of the channel through which updates are received.
+///
+/// Dropping or closing it makes the driver run to completion.
+#[derive(Debug)]
+pub struct UpdateStream<T> {
+    /// The receive end of the channel the driver sends updates to
+    receiver: mpsc::UnboundedReceiver<T>,
+    /// Signals the driver that this stream is closed when it is dropped
+    alive: Option<oneshot::Sender<()>>,
+    /// The health and counters recorded by the driver
+    statistics: Arc<Statistics>,
+}
+
+impl<T> UpdateStream<T> {
+    /// Creates a new [`UpdateStream`] reporting statistics together with the transmit end of its channel and a future that completes when it is closed
+    fn channel(statistics: Arc<Statistics>) -> (mpsc::UnboundedSender<T>, Closed, Self) {
+        let (tx, receiver) = mpsc::unbounded();
+        let (alive, closed) = oneshot::channel();
+        (
+            tx,
+            Box::pin(closed.map(drop)),
+            Self {
+                receiver,
+                alive: Some(alive),
+                statistics,
+            },
+        )
+    }
+
+    /// Receives the next update, returns [None] when the driver stopped
+    pub async fn recv(&mut self) -> Option<T> {
+        self.receiver.next().await
+    }
+
+    /// Closes the stream so the driver stops, updates which were already sent can still be received
+    pub fn close(&mut self) {
+        self.alive = None;
+        self.receiver.close();
+    }
+
+    /// Get the number of times the driver resynchronised its state with the system, see [`ConnectivityMonitor::resyncs`]
+    #[must_use]
+    pub fn resyncs(&self) -> u64 {
+        self.statistics.resyncs()
+    }
+
+    /// Get the number of times the driver restarted after it failed, see [`ConnectivityMonitorBuilder::supervise`]
+    #[must_use]
+    pub fn restarts(&self) -> u64 {
+        self.statistics.restarts()
+    }
+
+    /// Get the health of the driver
+    #[must_use]
+    pub fn health(&self) -> MonitorHealth {
+        self.statistics.health()
+    }
+}
+
+impl<T> Stream for UpdateStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+/// The [`UpdateStream`] through which connectivity updates are received
+pub type ConnectivityStream = UpdateStream<Connectivity>;
+/// The [`UpdateStream`] through which per interface connectivity updates are received
+pub type SnapshotStream = UpdateStream<Vec<InterfaceSnapshot>>;
+/// The [`UpdateStream`] through which network change events are received
+pub type EventStream = UpdateStream<Event>;
+/// The [`UpdateStream`] through which connectivity updates are received together with the key of their network namespace
+#[cfg(target_os = "linux")]
+pub type NamespaceStream<K> = UpdateStream<(K, Connectivity)>;
+
+/// Refers to a linux network namespace which is monitored instead of the network namespace of the process.
+///
+/// Entering a network namespace requires the `CAP_SYS_ADMIN` capability.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum NetworkNamespace {
+    /// The network namespace at a path like `/var/run/netns/NAME` or `/proc/PID/ns/net`
+    Path(PathBuf),
+    /// The network namespace an open file descriptor refers to
+    Fd(Arc<OwnedFd>),
+}
+
+/// A shareable handle to a single driver that monitors the connectivity of the system.
+///
+/// Use [`ConnectivityMonitor::builder`] to configure and create a driver.
+/// The driver runs to completion when all handles and the receivers they handed out are dropped.
+#[derive(Clone, Debug)]
+pub struct ConnectivityMonitor {
+    /// The receive end of the watch channel the driver keeps the current connectivity in
+    receiver: tokio::sync::watch::Receiver<Connectivity>,
+    /// The callbacks the driver calls when the connectivity changed
+    callbacks: Callbacks,
+    /// The counters the driver records
+    statistics: Arc<Statistics>,
+}
+
+impl ConnectivityMonitor {
+    /// Creates a [`ConnectivityMonitorBuilder`] with the default configuration
+    pub fn builder() -> ConnectivityMonitorBuilder {
+        ConnectivityMonitorBuilder::default()
+    }
+
+    /// Get the current connectivity
+    #[must_use]
+    pub fn current(&self) -> Connectivity {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until predicate holds for the connectivity, starting with the initial connectivity emitted by the driver
+    ///
+    /// # Returns
+    ///
+    /// The connectivity for which predicate holds.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver stopped before predicate holds.
+    pub async fn wait_until<F>(&self, mut predicate: F) -> Result<Connectivity, Error>
+    where
+        F: FnMut(&Connectivity) -> bool,
+    {
+        // this clone has not seen anything, so it has changed once the driver emitted the initial connectivity, even when the driver stopped since
+        let mut receiver = self.receiver.clone();
+        receiver.changed().await.map_err(|_closed| Error::Closed)?;
+        loop {
+            let connectivity = *receiver.borrow_and_update();
+            if predicate(&connectivity) {
+                return Ok(connectivity);
+            }
+            receiver.changed().await.map_err(|_closed| Error::Closed)?;
+        }
+    }
+
+    /// Waits until the internet is reachable over any ip type
+    ///
+    /// See [`ConnectivityMonitor::wait_until`] for details.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver stopped before the internet is reachable.
+    pub async fn wait_until_internet(&self) -> Result<Connectivity, Error> {
+        self.wait_until(|connectivity| connectivity.any() == ConnectivityState::Internet)
+            .await
+    }
+
+    /// Waits until predicate holds for the connectivity or deadline passed
+    ///
+    /// See [`ConnectivityMonitor::wait_until`] for details.
+    ///
+    /// # Returns
+    ///
+    /// The connectivity for which predicate holds or [None] when deadline passed first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver stopped before predicate holds.
+    pub async fn wait_until_deadline<F>(
+        &self,
+        deadline: Instant,
+        predicate: F,
+    ) -> Result<Option<Connectivity>, Error>
+    where
+        F: FnMut(&Connectivity) -> bool,
+    {
+        let period = deadline.saturating_duration_since(Instant::now());
+        runtime::timeout(period, self.wait_until(predicate))
+            .await
+            .map_or(Ok(None), |result| result.map(Some))
+    }
+
+    /// Get the number of times the driver resynchronised its state with the system because notifications were lost
+    ///
+    /// This happens when the system notifies faster than the driver can keep up with, only the linux driver can lose notifications.
+    #[must_use]
+    pub fn resyncs(&self) -> u64 {
+        self.statistics.resyncs()
+    }
+
+    /// Get the number of times the driver restarted after it failed, see [`ConnectivityMonitorBuilder::supervise`]
+    #[must_use]
+    pub fn restarts(&self) -> u64 {
+        self.statistics.restarts()
+    }
+
+    /// Get the health of the driver
+    #[must_use]
+    pub fn health(&self) -> MonitorHealth {
+        self.statistics.health()
+    }
+
+    /// Registers callback, which the driver calls with the old and the new connectivity every time the connectivity changed
+    ///
+    /// callback is called from the driver, so it should return quickly.
+    /// It is not called for the initial connectivity when that equals the placeholder without connectivity for both ip types.
+    ///
+    /// # Returns
+    ///
+    /// A [`CallbackHandle`] which unregisters callback when it is dropped.
+    pub fn on_change<F>(&self, callback: F) -> CallbackHandle
+    where
+        F: Fn(Connectivity, Connectivity) + Send + Sync + 'static,
+    {
+        let shared: Arc<Callback> = Arc::new(callback);
+        CallbackHandle {
+            id: self.callbacks.register(shared),
+            callbacks: self.callbacks.clone(),
+        }
+    }
+
+    /// Creates an independent receiver which starts with the current connectivity marked as seen
+    #[must_use]
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<Connectivity> {
+        let mut receiver = self.receiver.clone();
+        drop(receiver.borrow_and_update());
+        receiver
+    }
+}
+
+/// Keeps a callback registered with [`ConnectivityMonitor::on_change`] until it is dropped.
+#[derive(Debug)]
+#[must_use = "the callback is unregistered when the handle is dropped"]
+pub struct CallbackHandle {
+    /// The identifier of the callback
+    id: u64,
+    /// The callbacks the callback is registered in
+    callbacks: Callbacks,
+}
+
+impl Drop for CallbackHandle {
+    fn drop(&mut self) {
+        self.callbacks.unregister(self.id);
+    }
+}
+
+/// Configures and creates a driver.
+///
+/// By default all interfaces except loopback devices and both ip types contribute to the connectivity and updates are emitted without delay.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct ConnectivityMonitorBuilder {
+    /// The filter for the interfaces and ip types that contribute to the connectivity
+    filter: Filter,
+    /// The periods the connectivity must be stable before it is emitted
+    debounce: Debounce,
+    /// The settings for verifying the internet connectivity
+    probe: Settings,
+    /// The backoff with which the driver is restarted after it failed
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    restart: Option<Backoff>,
+    /// The network namespace that is monitored instead of the network namespace of the process
+    #[cfg(target_os = "linux")]
+    namespace: Option<NetworkNamespace>,
+}
+
+impl ConnectivityMonitorBuilder {
+    /// Sets whether loopback devices contribute to the connectivity
+    pub const fn loopback(mut self, loopback: bool) -> Self {
+        self.filter.loopback = loopback;
+        self
+    }
+
+    /// Adds an interface name to the interfaces that contribute to the connectivity.
+    ///
+    /// The name can be a glob pattern in which `*` matches any sequence of characters and `?` matches any single character, like `wlan*`.
+    /// When no interface is included, all interfaces contribute.
+    pub fn include_interface(mut self, name: &str) -> Self {
+        self.filter.include.insert(name.to_owned());
+        self
+    }
+
+    /// Adds an interface name to the interfaces that never contribute to the connectivity
+    ///
+    /// The name can be a glob pattern like in [`include_interface`](Self::include_interface), for example `docker*`.
+    pub fn exclude_interface(mut self, name: &str) -> Self {
+        self.filter.exclude.insert(name.to_owned());
+        self
+    }
+
+    /// Adds an interface kind to the kinds of interfaces that contribute to the connectivity.
+    ///
+    /// The kind is the link kind as shown by `ip -details link`, like `bridge`, `veth`, `tun`, `wireguard` or `vlan`.
+    /// Physical devices have no kind, so they don't contribute once a kind is included.
+    /// When no kind is included, all kinds contribute.
+    /// On windows the kind of an interface is unknown, so only interfaces without a kind filter contribute.
+    pub fn include_kind(mut self, kind: &str) -> Self {
+        self.filter.include_kinds.insert(kind.to_owned());
+        self
+    }
+
+    /// Adds an interface kind to the kinds of interfaces that never contribute to the connectivity
+    ///
+    /// See [`include_kind`](Self::include_kind) for the names of the kinds.
+    pub fn exclude_kind(mut self, kind: &str) -> Self {
+        self.filter.exclude_kinds.insert(kind.to_owned());
+        self
+    }
+
+    /// Sets whether interfaces that are a port of a bridge contribute to the connectivity
+    pub const fn bridge_ports(mut self, bridge_ports: bool) -> Self {
+        self.filter.bridge_ports = bridge_ports;
+        self
+    }
+
+    /// Sets whether ipv4 is monitored, when it isn't the ipv4 connectivity is always [`ConnectivityState::None`]
+    pub const fn ipv4(mut self, ipv4: bool) -> Self {
+        self.filter.ipv4 = ipv4;
+        self
+    }
+
+    /// Sets whether ipv6 is monitored, when it isn't the ipv6 connectivity is always [`ConnectivityState::None`]
+    pub const fn ipv6(mut self, ipv6: bool) -> Self {
+        self.filter.ipv6 = ipv6;
+        self
+    }
+
+    /// Sets whether permanent addresses contribute to the connectivity, which they do by default.
+    ///
+    /// Permanent addresses never expire, like statically configured addresses and the link-local addresses the system assigns itself.
+    /// Addresses assigned by DHCP or SLAAC have a lifetime and always contribute.
+    pub const fn permanent_addresses(mut self, permanent_addresses: bool) -> Self {
+        self.filter.permanent_addresses = permanent_addresses;
+        self
+    }
+
+    /// Sets the period an update must be stable before it is emitted.
+    ///
+    /// The initial update is always emitted without delay.
+    pub const fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Debounce {
+            upgrade: debounce,
+            downgrade: debounce,
+        };
+        self
+    }
+
+    /// Sets separate periods an update must be stable before it is emitted, depending on whether it raises or lowers the connectivity.
+    ///
+    /// An update that lowers the connectivity of any ip type is delayed by downgrade, other updates are delayed by upgrade.
+    /// A zero period emits those updates immediately, so `hysteresis(Duration::ZERO, Duration::from_secs(5))` reports upgrades immediately and downgrades only when they last 5 seconds.
+    /// A flap that returns to the emitted connectivity within the period is never reported.
+    ///
+    /// The initial update is always emitted without delay.
+    pub const fn hysteresis(mut self, upgrade: Duration, downgrade: Duration) -> Self {
+        self.debounce = Debounce { upgrade, downgrade };
+        self
+    }
+
+    /// Sets the probe that verifies the internet is reachable.
+    ///
+    /// When it is set, [`ConnectivityState::Internet`] is only reported for an ip type after the probe succeeded.
+    /// It is probed again every time the interfaces, addresses or gateways through which an ip type reaches the internet change and periodically while it has internet connectivity.
+    /// Until the probe of the changed paths succeeded, the ip type is reported as [`ConnectivityState::Network`].
+    /// A probe can't be combined with a [namespace](ConnectivityMonitorBuilder::namespace).
+    pub fn probe(mut self, probe: Probe) -> Self {
+        self.probe.probe = Some(probe);
+        self
+    }
+
+    /// Sets the period after which the probe is performed again, defaults to 5 minutes
+    pub const fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe.interval = interval;
+        self
+    }
+
+    /// Sets the period after which the probe is considered failed, defaults to 10 seconds
+    pub const fn probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe.timeout = timeout;
+        self
+    }
+
+    /// Restarts the driver after it failed instead of failing the future that must be awaited, so the receivers stay alive.
+    ///
+    /// The delay before a restart doubles for every failed attempt from initial_backoff up to max_backoff, and resets once the driver is running again.
+    /// While restarting, the last emitted update remains current and the health is [`MonitorHealth::Restarting`].
+    /// Only the linux driver restarts, the windows driver doesn't fail after it was created.
+    ///
+    /// The linux driver also resynchronises with this backoff when it lost its connection with the system, like when notifications overran the socket buffer.
+    /// Without supervision it only does so a few times in a row before it fails.
+    pub const fn supervise(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.restart = Some(Backoff {
+            initial: initial_backoff,
+            max: max_backoff,
+        });
+        self
+    }
+
+    /// Sets the network namespace that is monitored instead of the network namespace of the process.
+    ///
+    /// The namespace is entered only while the socket is created, so the thread that builds the driver returns to its own network namespace.
+    /// The driver panics when a thread couldn't return to its own network namespace, so it never silently stays in the monitored one.
+    /// Because the sockets of a [probe](ConnectivityMonitorBuilder::probe) would be created in the network namespace of the process, creating a driver fails when both are set.
+    #[cfg(target_os = "linux")]
+    pub fn namespace(mut self, namespace: NetworkNamespace) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    /// Creates a driver that sends connectivity updates to a channel.
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and a [`ConnectivityStream`] through which connectivity updates are received.
+    ///
+    /// # Notes
+    ///
+    /// When the stream is dropped or closed, the future will run to completion.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    /// The returned future can fail when the underlying driver received an error.
+    pub fn build(
+        self,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, ConnectivityStream), Error> {
+        self.stream(Arc::default())
+    }
+
+    /// Creates a driver that sends per interface connectivity updates to a channel.
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and a [`SnapshotStream`] through which interface updates are received.
+    /// Every update contains an [`InterfaceSnapshot`] for all known interfaces that contribute to the connectivity ordered by interface index.
+    /// A change of only the lifetimes of addresses, like a refresh by a router advertisement, isn't sent, so the lifetimes are those of the last update.
+    ///
+    /// # Notes
+    ///
+    /// When the stream is dropped or closed, the future will run to completion.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    /// The returned future can fail when the underlying driver received an error.
+    pub fn build_snapshots(
+        self,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, SnapshotStream), Error> {
+        let statistics = Arc::<Statistics>::default();
+        let (tx, closed, stream) = UpdateStream::channel(Arc::clone(&statistics));
+        Ok((
+            self.driver(Sender::Snapshots(tx), closed, statistics)?,
+            stream,
+        ))
+    }
+
+    /// Creates a driver that sends network change events to a channel.
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and an [`EventStream`] through which events are received.
+    /// Every event that changes the aggregated connectivity is followed by an [`Event::ConnectivityChanged`] with that event as its cause.
+    /// The driver starts out without any connectivity, so the initial connectivity is emitted as a change without a cause unless it has no connectivity at all.
+    /// When debouncing, only the [`Event::ConnectivityChanged`] events are delayed and their cause is the last event before the connectivity settled.
+    ///
+    /// # Notes
+    ///
+    /// When the stream is dropped or closed, the future will run to completion.
+    /// On windows a change can't be attributed to a specific event, so only [`Event::ConnectivityChanged`] events without a cause are emitted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    /// The returned future can fail when the underlying driver received an error.
+    pub fn build_events(
+        self,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, EventStream), Error> {
+        let statistics = Arc::<Statistics>::default();
+        let (tx, closed, stream) = UpdateStream::channel(Arc::clone(&statistics));
+        Ok((self.driver(Sender::Events(tx), closed, statistics)?, stream))
+    }
+
+    /// Creates a driver that keeps the current connectivity in a watch channel.
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and the receive end of a watch channel which holds the current connectivity.
+    /// The receiver can be cloned to share the driver between multiple tasks.
+    /// Until the driver emitted the initial connectivity, the channel holds no connectivity for both ip types.
+    /// The receiver is always notified of the initial connectivity, even when it is the same.
+    ///
+    /// # Notes
+    ///
+    /// When all receivers are dropped, the future will run to completion.
+    /// The receiver doesn't report the health of the driver, use [`ConnectivityMonitorBuilder::build_monitor`] for that.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    /// The returned future can fail when the underlying driver received an error.
+    pub fn build_watch(
+        self,
+    ) -> Result<
+        (
+            impl Future<Output = Result<(), Error>>,
+            tokio::sync::watch::Receiver<Connectivity>,
+        ),
+        Error,
+    > {
+        self.watch(Callbacks::default(), Arc::default())
+    }
+
+    /// Creates a driver that can be shared through a [`ConnectivityMonitor`].
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and a handle which hands out receivers for the current connectivity.
+    /// Until the driver emitted the initial connectivity, the handle holds no connectivity for both ip types.
+    ///
+    /// # Notes
+    ///
+    /// When all handles and receivers are dropped, the future will run to completion.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    /// The returned future can fail when the underlying driver received an error.
+    pub fn build_monitor(
+        self,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, ConnectivityMonitor), Error> {
+        let callbacks = Callbacks::default();
+        let statistics = Arc::<Statistics>::default();
+        let (driver, receiver) = self.watch(callbacks.clone(), Arc::clone(&statistics))?;
+        Ok((
+            driver,
+            ConnectivityMonitor {
+                receiver,
+                callbacks,
+                statistics,
+            },
+        ))
+    }
+
+    /// Creates a single driver that monitors several network namespaces with this configuration and sends their connectivity updates to a channel.
+    ///
+    /// # Returns
+    ///
+    /// The return value consists of a future that must be awaited and a [`NamespaceStream`] through which connectivity updates are received together with the key of their network namespace.
+    /// The initial connectivity of every network namespace is received separately.
+    ///
+    /// # Notes
+    ///
+    /// When the stream is dropped or closed, the future will run to completion.
+    /// The drivers of all network namespaces record their health and counters in the same stream, so it reports the health of the driver that changed it last.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the network namespaces couldn't be entered or the underlying driver failed in some way.
+    /// The returned future fails when any of the underlying drivers failed.
+    #[cfg(target_os = "linux")]
+    pub fn build_namespaces<K, I>(
+        self,
+        namespaces: I,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, NamespaceStream<K>), Error>
+    where
+        I: IntoIterator<Item = (K, NetworkNamespace)>,
+        K: Clone + Send + 'static,
+    {
+        let statistics = Arc::<Statistics>::default();
+        let mut drivers = Vec::new();
+        let mut streams = Vec::new();
+        for (key, namespace) in namespaces {
+            let (driver, stream) = self
+                .clone()
+                .namespace(namespace)
+                .stream(Arc::clone(&statistics))?;
+            drivers.push(driver);
+            streams.push(Box::pin(
+                stream.map(move |connectivity| (key.clone(), connectivity)),
+            ));
+        }
+
+        let (tx, closed, stream) = UpdateStream::channel(statistics);
+        let forward = async move {
+            let mut updates = futures::stream::select_all(streams);
+            while let Some(update) = updates.next().await {
+                if tx.unbounded_send(update).is_err() {
+                    break;
+                }
+            }
+        };
+        let driver = async move {
+            futures::select_biased! {
+                _ = closed.fuse() => Ok(()),
+                result = futures::future::try_join_all(drivers).fuse() => result.map(drop),
+                _ = forward.fuse() => Ok(()),
+            }
+        };
+        Ok((driver, stream))
+    }
+
+    /// Gets the current connectivity once without subscribing to any updates or spawning anything.
+    ///
+    /// The probe is not performed, so the connectivity is derived from the routes only.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the current state couldn't be retrieved from the system.
+    #[cfg_attr(target_os = "windows", allow(clippy::unused_async))]
+    pub async fn current_connectivity(self) -> Result<Connectivity, Error> {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                linux::current(self.filter, self.namespace.as_ref()).await
+            } else if #[cfg(target_os = "windows")] {
+                windows::current(&self.filter)
+            } else {
+                compile_error!("This crate has no implementation for this configuration.");
+            }
+        }
+    }
+
+    /// Gets the current connectivity once, blocking the current thread until it is known.
+    ///
+    /// See [`ConnectivityMonitorBuilder::current_connectivity`] for details.
+    ///
+    /// # Panics
+    ///
+    /// With the `runtime-tokio` feature, this function panics when it is called from within an asynchronous execution context.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the current state couldn't be retrieved from the system.
+    pub fn current_connectivity_blocking(self) -> Result<Connectivity, Error> {
+        runtime::block_on(self.current_connectivity()).map_err(Error::Runtime)?
+    }
+
+    /// Runs the driver on a background thread, so connectivity updates can be received without an async runtime.
+    ///
+    /// The background thread runs its own runtime, the driver is stopped and the thread is joined when the returned [`blocking::Monitor`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the background thread couldn't be spawned or the underlying driver failed in some way.
+    pub fn build_blocking(self) -> Result<blocking::Monitor, Error> {
+        blocking::Monitor::spawn(self, drop)
+    }
+
+    /// Runs the driver on a background thread like [`ConnectivityMonitorBuilder::build_blocking`], calling callback on that thread for every connectivity update.
+    ///
+    /// The update is received through [`blocking::Monitor::receiver`] as well after callback returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the background thread couldn't be spawned or the underlying driver failed in some way.
+    pub fn build_blocking_with_callback<F>(self, callback: F) -> Result<blocking::Monitor, Error>
+    where
+        F: FnMut(Connectivity) + Send + 'static,
+    {
+        blocking::Monitor::spawn(self, callback)
+    }
+
+    /// Creates a driver that sends connectivity updates to a channel and records its counters in statistics.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    fn stream(
+        self,
+        statistics: Arc<Statistics>,
+    ) -> Result<(impl Future<Output = Result<(), Error>>, ConnectivityStream), Error> {
+        let (tx, closed, stream) = UpdateStream::channel(Arc::clone(&statistics));
+        Ok((
+            self.driver(Sender::Connectivity(tx), closed, statistics)?,
+            stream,
+        ))
+    }
+
+    /// Creates a driver that keeps the current connectivity in a watch channel, calls callbacks when it changed and records its counters in statistics.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way.
+    fn watch(
+        self,
+        callbacks: Callbacks,
+        statistics: Arc<Statistics>,
+    ) -> Result<
+        (
+            impl Future<Output = Result<(), Error>>,
+            tokio::sync::watch::Receiver<Connectivity>,
+        ),
+        Error,
+    > {
+        let (watch_tx, rx) = tokio::sync::watch::channel(Connectivity {
+            ipv4: ConnectivityState::None,
+            ipv6: ConnectivityState::None,
+        });
+        let tx = Arc::new(watch_tx);
+        let closing_tx = Arc::clone(&tx);
+        let closed = Box::pin(async move { closing_tx.closed().await });
+        Ok((
+            self.driver(Sender::Watch(tx, callbacks), closed, statistics)?,
+            rx,
+        ))
+    }
+
+    /// Creates the driver for the current platform that emits its updates to sender until closed completes and records its counters in statistics.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the underlying driver failed in some way or a probe is combined with a namespace.
+    fn driver(
+        self,
+        sender: Sender,
+        closed: Closed,
+        statistics: Arc<Statistics>,
+    ) -> Result<impl Future<Output = Result<(), Error>>, Error> {
+        #[cfg(target_os = "linux")]
+        if self.namespace.is_some() && self.probe.probe.is_some() {
+            return Err(Error::Probe(
+                "a probe can't verify the connectivity of another network namespace".into(),
+            ));
+        }
+        let emitter = Emitter::new(
+            sender,
+            closed,
+            self.debounce,
+            Prober::new(self.probe)?,
+            Arc::clone(&statistics),
+        );
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                let driver = linux::new(emitter, self.filter, self.restart, self.namespace)?;
+            } else if #[cfg(target_os = "windows")] {
+                let driver = windows::new(emitter, self.filter)?;
+            } else {
+                compile_error!("This crate has no implementation for this configuration.");
+            }
+        }
+        Ok(async move {
+            let result = driver.await;
+            if result.is_err() {
+                statistics.set_health(MonitorHealth::Failed);
+            }
+            result
+        })
+    }
+}
+
+/// Creates a driver with the default configuration that sends connectivity updates to a channel.
+///
+/// See [`ConnectivityMonitorBuilder::build`] for details.
+///
+/// # Errors
+///
+/// This function will return an error if the underlying driver failed in some way.
+/// The returned future can fail when the underlying driver received an error.
+pub fn new() -> Result<(impl Future<Output = Result<(), Error>>, ConnectivityStream), Error> {
+    ConnectivityMonitor::builder().build()
+}
+
+/// Creates a driver with the default configuration that sends per interface connectivity updates to a channel.
+///
+/// See [`ConnectivityMonitorBuilder::build_snapshots`] for details.
+///
+/// # Errors
+///
+/// This function will return an error if the underlying driver failed in some way.
+/// The returned future can fail when the underlying driver received an error.
+pub fn new_snapshots() -> Result<(impl Future<Output = Result<(), Error>>, SnapshotStream), Error> {
+    ConnectivityMonitor::builder().build_snapshots()
+}
+
+/// Creates a driver with the default configuration that sends network change events to a channel.
 ///
-/// # Returns
+/// See [`ConnectivityMonitorBuilder::build_events`] for details.
 ///
-/// The return value consists of a future that must be awaited and the receive end of a channel through which connectivity updates are received.
+/// # Errors
 ///
-/// # Notes
+/// This function will return an error if the underlying driver failed in some way.
+/// The returned future can fail when the underlying driver received an error.
+pub fn new_events() -> Result<(impl Future<Output = Result<(), Error>>, EventStream), Error> {
+    ConnectivityMonitor::builder().build_events()
+}
+
+/// Creates a driver with the default configuration that keeps the current connectivity in a watch channel.
 ///
-/// When the receive end of the channel is dropped, the future will run to completion.
+/// See [`ConnectivityMonitorBuilder::build_watch`] for details.
 ///
 /// # Errors
 ///
 /// This function will return an error if the underlying driver failed in some way.
 /// The returned future can fail when the underlying driver received an error.
-pub fn new() -> Result<
+pub fn new_watch() -> Result<
     (
-        impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>>,
-        tokio::sync::mpsc::UnboundedReceiver<Connectivity>,
+        impl Future<Output = Result<(), Error>>,
+        tokio::sync::watch::Receiver<Connectivity>,
     ),
-    Box<dyn Error + Send + Sync>,
+    Error,
 > {
-    cfg_if::cfg_if! {
-        if #[cfg(target_os = "linux")] {
-            linux::new()
-        } else if #[cfg(target_os = "windows")] {
-            windows::new()
-        } else {
-            compile_error!("This crate has no implementation for this configuration.");
+    ConnectivityMonitor::builder().build_watch()
+}
+
+/// Creates a driver with the default configuration that can be shared through a [`ConnectivityMonitor`].
+///
+/// See [`ConnectivityMonitorBuilder::build_monitor`] for details.
+///
+/// # Errors
+///
+/// This function will return an error if the underlying driver failed in some way.
+/// The returned future can fail when the underlying driver received an error.
+pub fn new_monitor() -> Result<(impl Future<Output = Result<(), Error>>, ConnectivityMonitor), Error>
+{
+    ConnectivityMonitor::builder().build_monitor()
+}
+
+/// Runs a driver with the default configuration on a background thread.
+///
+/// See [`ConnectivityMonitorBuilder::build_blocking`] for details.
+///
+/// # Errors
+///
+/// This function will return an error if the background thread couldn't be spawned or the underlying driver failed in some way.
+pub fn new_blocking() -> Result<blocking::Monitor, Error> {
+    ConnectivityMonitor::builder().build_blocking()
+}
+
+/// Gets the current connectivity once with the default configuration.
+///
+/// See [`ConnectivityMonitorBuilder::current_connectivity`] for details.
+///
+/// # Errors
+///
+/// This function will return an error if the current state couldn't be retrieved from the system.
+pub async fn current_connectivity() -> Result<Connectivity, Error> {
+    ConnectivityMonitor::builder().current_connectivity().await
+}
+
+/// Gets the current connectivity once with the default configuration, blocking the current thread until it is known.
+///
+/// See [`ConnectivityMonitorBuilder::current_connectivity_blocking`] for details.
+///
+/// # Panics
+///
+/// This function panics when it is called from within an asynchronous execution context.
+///
+/// # Errors
+///
+/// This function will return an error if the current state couldn't be retrieved from the system.
+pub fn current_connectivity_blocking() -> Result<Connectivity, Error> {
+    ConnectivityMonitor::builder().current_connectivity_blocking()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a handle to a driver that emitted connectivity, if any, and stopped
+    fn stopped(connectivity: Option<Connectivity>) -> ConnectivityMonitor {
+        let (sender, receiver) = tokio::sync::watch::channel(Connectivity {
+            ipv4: ConnectivityState::None,
+            ipv6: ConnectivityState::None,
+        });
+        if let Some(emitted) = connectivity {
+            sender.send_replace(emitted);
         }
+        ConnectivityMonitor {
+            receiver,
+            callbacks: Callbacks::default(),
+            statistics: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn last_connectivity_of_a_stopped_driver_is_waited_for() {
+        let connectivity = Connectivity {
+            ipv4: ConnectivityState::Internet,
+            ipv6: ConnectivityState::None,
+        };
+        let monitor = stopped(Some(connectivity));
+        let reached = runtime::block_on(monitor.wait_until_internet()).unwrap();
+        assert_eq!(reached.unwrap(), connectivity);
+        let unreached = runtime::block_on(
+            monitor.wait_until(|current| current.ipv6 == ConnectivityState::Internet),
+        )
+        .unwrap();
+        assert!(matches!(unreached, Err(Error::Closed)));
+    }
+
+    #[test]
+    fn stopped_driver_without_connectivity_is_closed() {
+        let monitor = stopped(None);
+        let waited = runtime::block_on(monitor.wait_until(|_| true)).unwrap();
+        assert!(matches!(waited, Err(Error::Closed)));
     }
 }
@@ -3,122 +3,471 @@
 //! The linux implementation for this crate using rt-netlink.
 
 use crate::{
-    state::{AddressInfo, Interfaces, LinkInfo, RouteInfo},
-    Connectivity,
+    runtime::{self, Socket},
+    sender::{Backoff, Closed, Emitter},
+    state::{AddressInfo, Filter, Interfaces, LinkInfo, RouteGateway, RouteInfo, Scope},
+    AddressState, Connectivity, Error, Event, LinkState, NetworkNamespace,
 };
-use core::fmt::Display;
-use futures::{channel::mpsc::UnboundedReceiver, stream::StreamExt, Future, TryStreamExt};
-use log::debug;
+use core::{mem, time::Duration};
+use futures::{
+    channel::mpsc::UnboundedReceiver,
+    future::{self, Shared},
+    select_biased,
+    stream::StreamExt,
+    Future, FutureExt, TryStreamExt,
+};
+use log::{debug, warn};
 use rtnetlink::{
-    new_connection,
+    new_connection_with_socket,
     packet::{
         constants::{
-            self, AF_INET, AF_INET6, IFF_LOOPBACK, IFF_LOWER_UP, RTNLGRP_IPV4_IFADDR,
-            RTNLGRP_IPV4_ROUTE, RTNLGRP_IPV6_IFADDR, RTNLGRP_IPV6_ROUTE, RTNLGRP_LINK,
+            self, AF_BRIDGE, AF_INET, AF_INET6, IFF_DORMANT, IFF_LOOPBACK, IFF_LOWER_UP,
+            IFF_RUNNING, IFF_UP, RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV4_ROUTE, RTNLGRP_IPV6_IFADDR,
+            RTNLGRP_IPV6_ROUTE, RTNLGRP_LINK,
+        },
+        nlas::{
+            self,
+            address::{CacheInfo, CacheInfoBuffer},
+            Nla, NlasIterator,
         },
-        nlas, AddressMessage, LinkMessage, RouteMessage, RtnlMessage,
+        traits::Parseable,
+        AddressMessage, LinkMessage, NetlinkBuffer, RouteMessage, RtnlMessage,
     },
-    proto::{NetlinkMessage, NetlinkPayload},
-    sys::{AsyncSocket, SocketAddr},
+    proto::{self, NetlinkMessage, NetlinkPayload},
+    sys::{protocols::NETLINK_ROUTE, AsyncSocket, AsyncSocketExt, SocketAddr},
     Handle, IpVersion,
 };
 use std::{
-    error::Error,
+    collections::HashMap,
+    fs::File,
+    io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::unix::io::{AsRawFd, OwnedFd, RawFd},
+    time::Instant,
+};
+
+/// The rtnetlink connection, its handle, the receive end for the messages of the joined multicast groups and the nexthop objects
+type Connection = (
+    proto::Connection<RtnlMessage, Socket>,
+    Handle,
+    UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+    NextHops,
+);
+/// A default route that refers to a nexthop object, by whether it is an ipv6 route, the id of the object and the priority of the route
+type Reference = (bool, u32, u32);
+
+/// The route attribute with the id of the nexthop object a route refers to
+const RTA_NH_ID: u16 = 30;
+/// The message type of a nexthop object
+const RTM_NEWNEXTHOP: u16 = 104;
+/// The message type of a removed nexthop object
+const RTM_DELNEXTHOP: u16 = 105;
+/// The message type of a request for nexthop objects
+const RTM_GETNEXTHOP: u16 = 106;
+/// The length of the header of a nexthop message
+const NEXTHOP_HEADER_LEN: usize = 8;
+/// The multicast group of changes to nexthop objects
+const RTNLGRP_NEXTHOP: u32 = 32;
+/// The nexthop attribute with the id of the nexthop object
+const NHA_ID: u16 = 1;
+/// The nexthop attribute with the members of a nexthop group
+const NHA_GROUP: u16 = 2;
+/// The nexthop attribute with the output interface of the nexthop
+const NHA_OIF: u16 = 5;
+/// The nexthop attribute with the gateway of the nexthop
+const NHA_GATEWAY: u16 = 6;
+/// The length of a single member of a nexthop group
+const NEXTHOP_GROUP_MEMBER_LEN: usize = 8;
+/// The length of the header of a netlink message
+const NETLINK_HEADER_LEN: usize = 16;
+/// The flags of a netlink dump request
+const NLM_F_REQUEST_DUMP: u16 = 0x301;
+/// The message type of a netlink error or acknowledgement
+const NLMSG_ERROR: u16 = 2;
+/// The message type that ends a netlink dump
+const NLMSG_DONE: u16 = 3;
+
+/// The backoff with which a lost rtnetlink connection is recreated when the driver isn't supervised
+const RECONNECT_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_millis(100),
+    max: Duration::from_secs(30),
 };
+/// The number of times in a row a lost rtnetlink connection is recreated before the driver fails when it isn't supervised
+const RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Converts an error of a dump request to an [Error], keeping the netlink error code when there is one
+fn dump_error(error: rtnetlink::Error) -> Error {
+    if let rtnetlink::Error::NetlinkError(ref message) = error {
+        Error::Netlink(message.to_io())
+    } else {
+        Error::Dump(Box::new(error))
+    }
+}
 
 /// Converts a vector to an array.
 fn vec_to_array<T, const N: usize>(v: Vec<T>) -> Result<[T; N], Vec<T>> {
     v.try_into()
 }
 
-/// assigns to assign from assignee only when they are different,
+/// Moves the calling thread into the network namespace fd refers to.
 ///
-/// # Returns
+/// # Errors
 ///
-/// true if the arguments were different and false otherwise
-fn diff_assign<T>(assign: &mut T, assignee: T) -> bool
-where
-    T: Eq,
-{
-    if *assign == assignee {
-        false
+/// This function will return an error if the namespace couldn't be entered.
+fn set_namespace(fd: RawFd) -> Result<(), Error> {
+    // SAFETY:
+    // setns only reads fd, which stays open for the duration of this call
+    if unsafe { libc::setns(fd, libc::CLONE_NEWNET) } == 0 {
+        Ok(())
     } else {
-        *assign = assignee;
-        true
+        Err(Error::Namespace(io::Error::last_os_error()))
     }
 }
 
-/// Creates a connection with rtnetlink and sends connectivity updates.
+/// Returns the calling thread to the network namespace it was in before it entered another one when it is dropped.
+struct EnteredNamespace {
+    /// The network namespace the calling thread was in before
+    original: File,
+}
+impl Drop for EnteredNamespace {
+    // a thread that stays in the other namespace, which can be a worker of a shared runtime, would silently create all its later sockets there
+    #[allow(clippy::panic)]
+    fn drop(&mut self) {
+        if let Err(error) = set_namespace(self.original.as_raw_fd()) {
+            panic!("couldn't return to the original network namespace {error}");
+        }
+    }
+}
+
+/// Calls create with the calling thread in namespace, so the sockets it creates belong to namespace.
 ///
-/// # Returns
+/// The calling thread returns to its own namespace before this function returns, also when create panics.
 ///
-/// The return value consists of a future that must be awaited and the receive end of a channel through which connectivity updates are received.
+/// # Panics
 ///
-/// # Notes
+/// This function panics when the calling thread couldn't return to its own namespace.
+///
+/// # Errors
 ///
-/// When the receive end of the channel is dropped, the future will run to completion.
+/// This function will return an error if namespace couldn't be entered.
+fn in_namespace<T, F>(namespace: Option<&NetworkNamespace>, create: F) -> Result<T, Error>
+where
+    F: FnOnce() -> T,
+{
+    let target = match namespace {
+        Some(target) => target,
+        None => return Ok(create()),
+    };
+    let target_fd: OwnedFd = match *target {
+        NetworkNamespace::Path(ref path) => File::open(path).map_err(Error::Namespace)?.into(),
+        NetworkNamespace::Fd(ref fd) => fd.try_clone().map_err(Error::Namespace)?,
+    };
+    let original = File::open("/proc/thread-self/ns/net").map_err(Error::Namespace)?;
+
+    debug!("entering network namespace {:?}", target);
+    set_namespace(target_fd.as_raw_fd())?;
+    let _entered = EnteredNamespace { original };
+    Ok(create())
+}
+
+/// Creates a connection with rtnetlink in namespace which joins the multicast groups for the state that passes filter, together with the [`NextHops`] which follow the changes to nexthop objects.
 ///
 /// # Errors
 ///
-/// This function will return an error if the rtnetlink connection failed or memberships couldn't be added.
-/// The returned future can fail when a rtnetlink error was received.
-pub fn new() -> Result<
-    (
-        impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>>,
-        tokio::sync::mpsc::UnboundedReceiver<Connectivity>,
-    ),
-    Box<dyn Error + Send + Sync>,
-> {
+/// This function will return an error if namespace couldn't be entered, the rtnetlink connection failed or memberships couldn't be added.
+fn connect(filter: &Filter, namespace: Option<&NetworkNamespace>) -> Result<Connection, Error> {
     debug!("creating rtnetlink connection");
-    let (mut conn, handle, messages) = new_connection()?;
+    let (mut conn, handle, messages) =
+        in_namespace(namespace, new_connection_with_socket::<Socket>)?.map_err(Error::Socket)?;
 
     debug!("add group membership for rtnetlink");
-    let groups = vec![
-        RTNLGRP_LINK,
-        RTNLGRP_IPV4_IFADDR,
-        RTNLGRP_IPV6_IFADDR,
-        RTNLGRP_IPV4_ROUTE,
-        RTNLGRP_IPV6_ROUTE,
-    ];
+    let mut groups = vec![RTNLGRP_LINK];
+    if filter.ipv4 {
+        groups.extend([RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV4_ROUTE]);
+    }
+    if filter.ipv6 {
+        groups.extend([RTNLGRP_IPV6_IFADDR, RTNLGRP_IPV6_ROUTE]);
+    }
     for group in groups {
-        conn.socket_mut().socket_mut().add_membership(group)?;
+        conn.socket_mut()
+            .socket_mut()
+            .add_membership(group)
+            .map_err(|source| Error::Membership { group, source })?;
     }
+    let next_hops = NextHops::new(namespace, true)?;
 
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    Ok((conn, handle, messages, next_hops))
+}
 
-    let checker = check_internet_connectivity(handle, messages, tx);
+/// Creates a connection with rtnetlink in namespace and sends updates through emitter for the state that passes filter.
+///
+/// The network namespace of the process is monitored when namespace isn't set.
+///
+/// # Returns
+///
+/// The return value is a future that must be awaited.
+///
+/// # Notes
+///
+/// When the receive end of the sender of emitter is dropped, the future will run to completion.
+///
+/// # Errors
+///
+/// This function will return an error if namespace couldn't be entered, the rtnetlink connection failed or memberships couldn't be added.
+/// The returned future can fail when a rtnetlink error was received or the connection was lost too often and the driver isn't restarted with restart.
+pub fn new(
+    emitter: Emitter,
+    filter: Filter,
+    restart: Option<Backoff>,
+    namespace: Option<NetworkNamespace>,
+) -> Result<impl Future<Output = Result<(), Error>>, Error> {
+    let connection = connect(&filter, namespace.as_ref())?;
+    Ok(drive(connection, emitter, filter, restart, namespace))
+}
 
-    #[allow(clippy::arithmetic_side_effects, clippy::integer_arithmetic)]
-    let driver = async {
+/// Checks the connectivity over connection until the receive end of the sender of emitter is dropped.
+///
+/// When the connection is lost, like when the socket buffer overran, a new connection is created and the state is resynchronised after the delay of [`resync_delay`].
+/// When the check fails and restart is set, a new connection is created after the backoff delay.
+///
+/// # Errors
+///
+/// This function will return an error if a new rtnetlink connection failed, a rtnetlink error was received or the connection was lost too often and restart isn't set.
+async fn drive(
+    mut connection: Connection,
+    mut emitter: Emitter,
+    filter: Filter,
+    restart: Option<Backoff>,
+    namespace: Option<NetworkNamespace>,
+) -> Result<(), Error> {
+    let mut attempt: u32 = 0;
+    let mut lost: u32 = 0;
+    loop {
+        let (conn, handle, messages, mut next_hops) = connection;
+        let connected = Instant::now();
         debug!("waiting on rtnetlink connection or connectivity checker");
         // waiting for both of these futures can be done with a select because when one finishes the other one will not do anymore meaningful work and can be dropped.
-        tokio::select! {
-            biased;
-            r_check = checker => {
-                r_check?;
-            },
-            _ = conn => (),
+        let result = select_biased! {
+            r_check = check_internet_connectivity(handle, messages, &mut emitter, filter.clone(), &mut next_hops).fuse() => r_check,
+            _ = conn.fuse() => Ok(false),
         };
         debug!("done waiting on rtnetlink connection or connectivity checker");
+        let mut failure = match result {
+            Ok(true) => return Ok(()),
+            Ok(false) => {
+                if connected.elapsed() > restart.unwrap_or(RECONNECT_BACKOFF).max {
+                    lost = 0;
+                }
+                let delay = resync_delay(restart, lost).ok_or_else(|| {
+                    Error::Socket(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "rtnetlink connection lost too often",
+                    ))
+                })?;
+                lost = lost.saturating_add(1);
+                warn!("rtnetlink connection lost, resynchronising in {delay:?}");
+                if wait(emitter.closed(), delay).await {
+                    return Ok(());
+                }
+                emitter.resynced();
+                None
+            }
+            Err(error) => Some(error),
+        };
 
-        Ok(())
+        connection = loop {
+            if let Some(error) = failure.take() {
+                let backoff = match (restart, &error) {
+                    (
+                        Some(backoff),
+                        &(Error::Namespace(_)
+                        | Error::Socket(_)
+                        | Error::Membership { .. }
+                        | Error::Dump(_)
+                        | Error::Netlink(_)),
+                    ) => backoff,
+                    _ => return Err(error),
+                };
+                if emitter.restarting() {
+                    attempt = 0;
+                }
+                let delay = backoff.delay(attempt);
+                attempt = attempt.saturating_add(1);
+                warn!("rtnetlink driver failed {error}, restarting in {delay:?}");
+                if wait(emitter.closed(), delay).await {
+                    return Ok(());
+                }
+            }
+            match connect(&filter, namespace.as_ref()) {
+                Ok(new_connection) => break new_connection,
+                Err(error) => failure = Some(error),
+            }
+        };
+    }
+}
+
+/// Get the delay before the state is resynchronised over a new connection after the connection was lost lost times in a row.
+///
+/// Only a supervised driver, which has restart set, resynchronises indefinitely.
+///
+/// # Returns
+///
+/// The delay or none when the driver gives up.
+fn resync_delay(restart: Option<Backoff>, lost: u32) -> Option<Duration> {
+    restart.map_or_else(
+        || (lost < RECONNECT_ATTEMPTS).then(|| RECONNECT_BACKOFF.delay(lost)),
+        |backoff| Some(backoff.delay(lost)),
+    )
+}
+
+/// Waits for delay unless closed completes first.
+///
+/// # Returns
+///
+/// Whether closed completed, so the receive end of the channel was dropped.
+async fn wait(mut closed: Shared<Closed>, delay: Duration) -> bool {
+    let elapsed = async {
+        match Instant::now().checked_add(delay) {
+            Some(deadline) => runtime::sleep_until(deadline).await,
+            None => future::pending().await,
+        }
     };
+    select_biased! {
+        _ = closed => {
+            debug!("transmit channel closed");
+            true
+        },
+        _ = elapsed.fuse() => false,
+    }
+}
+
+/// Creates a connection with rtnetlink in namespace and gets the connectivity for the state that passes filter without subscribing to any updates.
+///
+/// # Errors
+///
+/// This function will return an error if namespace couldn't be entered, the rtnetlink connection failed or any of the underlying requests has an error.
+pub async fn current(
+    filter: Filter,
+    namespace: Option<&NetworkNamespace>,
+) -> Result<Connectivity, Error> {
+    debug!("creating rtnetlink connection");
+    let (conn, handle, _) =
+        in_namespace(namespace, new_connection_with_socket::<Socket>)?.map_err(Error::Socket)?;
 
-    Ok((driver, rx))
+    let mut next_hops = NextHops::new(namespace, false)?;
+    let connectivity = select_biased! {
+        state = get_state(&handle, filter, &mut next_hops).fuse() => state?.connectivity(),
+        _ = conn.fuse() => return Err(Error::Dump("rtnetlink connection closed".into())),
+    };
+    Ok(connectivity)
 }
 
 /// Extract useful information from a [`LinkMessage`].
-const fn parse_link(link: &LinkMessage) -> LinkInfo {
-    (
-        link.header.index,
-        link.header.flags & IFF_LOOPBACK != 0,
-        link.header.flags & IFF_LOWER_UP != 0,
-    )
+///
+/// Has a result unless the message is about a bridge port in the bridge address family, which lacks the kind of the interface.
+fn parse_link(link: &LinkMessage) -> Option<LinkInfo> {
+    if u16::from(link.header.interface_family) == AF_BRIDGE {
+        return None;
+    }
+    let name = link.nlas.iter().find_map(|nla| {
+        if let nlas::link::Nla::IfName(ref name) = *nla {
+            Some(name.clone())
+        } else {
+            None
+        }
+    });
+    let mac = link.nlas.iter().find_map(|nla| {
+        if let nlas::link::Nla::Address(ref mac) = *nla {
+            Some(mac.clone())
+        } else {
+            None
+        }
+    });
+    let mtu = link.nlas.iter().find_map(|nla| {
+        if let nlas::link::Nla::Mtu(mtu) = *nla {
+            Some(mtu)
+        } else {
+            None
+        }
+    });
+    let infos = link.nlas.iter().find_map(|nla| {
+        if let nlas::link::Nla::Info(ref infos) = *nla {
+            Some(infos.as_slice())
+        } else {
+            None
+        }
+    });
+    let kind = infos.and_then(|found| {
+        found.iter().find_map(|info| {
+            if let nlas::link::Info::Kind(ref kind) = *info {
+                Some(kind_name(kind))
+            } else {
+                None
+            }
+        })
+    });
+    let bridge_port = infos.map_or(false, |found| {
+        found.iter().any(|info| {
+            if let nlas::link::Info::SlaveKind(ref slave_kind) = *info {
+                slave_kind.split(|&byte| byte == 0).next() == Some(b"bridge".as_slice())
+            } else {
+                false
+            }
+        })
+    });
+    Some(LinkInfo {
+        index: link.header.index,
+        name,
+        loopback: link.header.flags & IFF_LOOPBACK != 0,
+        state: link_state(link),
+        mac,
+        mtu,
+        kind,
+        bridge_port,
+    })
+}
+/// Derive the [`LinkState`] of a [`LinkMessage`] from its flags and operational state
+fn link_state(link: &LinkMessage) -> LinkState {
+    let flags = link.header.flags;
+    if flags & IFF_UP == 0 {
+        return LinkState::Down;
+    }
+    let operational = link.nlas.iter().find_map(|nla| {
+        if let nlas::link::Nla::OperState(state) = *nla {
+            Some(state)
+        } else {
+            None
+        }
+    });
+    match operational {
+        Some(nlas::link::State::Up) => LinkState::Up,
+        Some(nlas::link::State::Dormant) => LinkState::Dormant,
+        Some(nlas::link::State::Testing) => LinkState::Testing,
+        Some(nlas::link::State::LowerLayerDown) => LinkState::LowerLayerDown,
+        Some(nlas::link::State::Down | nlas::link::State::NotPresent) => LinkState::Down,
+        // drivers that don't track the operational state report it as unknown, like loopback devices
+        Some(nlas::link::State::Unknown | nlas::link::State::Other(_)) | None => {
+            let dormant = flags & IFF_DORMANT != 0;
+            let running = flags & IFF_LOWER_UP != 0 && flags & IFF_RUNNING != 0;
+            match (dormant, running) {
+                (true, _) => LinkState::Dormant,
+                (false, true) => LinkState::Up,
+                (false, false) => LinkState::LowerLayerDown,
+            }
+        }
+    }
+}
+/// Get the name of an [`InfoKind`](nlas::link::InfoKind) as the kernel reports it
+fn kind_name(kind: &nlas::link::InfoKind) -> String {
+    let mut name = vec![0; kind.value_len()];
+    kind.emit_value(&mut name);
+    // the name is nul terminated
+    name.pop();
+    String::from_utf8_lossy(&name).into_owned()
 }
 /// Extract useful information from an [`AddressMessage`].
 ///
-/// Has a valid result if the address is not permanent and actually has an address.
+/// Has a valid result if the message actually has an address.
 fn parse_address(addr: &AddressMessage) -> Option<AddressInfo> {
     let address = addr.nlas.iter().find_map(|nla| {
         if let nlas::address::Nla::Address(ref address) = *nla {
@@ -147,69 +496,509 @@ fn parse_address(addr: &AddressMessage) -> Option<AddressInfo> {
         ))),
         _ => None,
     }?;
-    (flags & constants::IFA_F_PERMANENT == 0).then_some((addr.header.index, ip_address))
+    let dad_failed = flags & constants::IFA_F_DADFAILED != 0;
+    let tentative = flags & constants::IFA_F_TENTATIVE != 0;
+    let optimistic = flags & constants::IFA_F_OPTIMISTIC != 0;
+    let deprecated = flags & constants::IFA_F_DEPRECATED != 0;
+    let state = match (dad_failed, tentative, optimistic, deprecated) {
+        (true, _, _, _) => AddressState::Duplicate,
+        (false, true, true, _) => AddressState::Optimistic,
+        (false, true, false, _) => AddressState::Tentative,
+        (false, false, _, true) => AddressState::Deprecated,
+        (false, false, _, false) => AddressState::Preferred,
+    };
+    let scope = match addr.header.scope {
+        constants::RT_SCOPE_LINK => Scope::Link,
+        constants::RT_SCOPE_HOST | constants::RT_SCOPE_NOWHERE => Scope::Host,
+        _ => Scope::Global,
+    };
+    let cache_info = addr.nlas.iter().find_map(|nla| {
+        if let nlas::address::Nla::CacheInfo(ref bytes) = *nla {
+            CacheInfoBuffer::new_checked(bytes)
+                .and_then(|buffer| CacheInfo::parse(&buffer))
+                .ok()
+        } else {
+            None
+        }
+    });
+    let now = Instant::now();
+    Some(AddressInfo {
+        index: addr.header.index,
+        address: ip_address,
+        state,
+        scope,
+        preferred_until: cache_info
+            .as_ref()
+            .and_then(|info| expires(now, info.ifa_preferred)),
+        valid_until: cache_info
+            .as_ref()
+            .and_then(|info| expires(now, info.ifa_valid)),
+        permanent: flags & constants::IFA_F_PERMANENT != 0,
+    })
+}
+/// Get the instant a lifetime in seconds starting at now expires, which is never for a lifetime of `u32::MAX` that the kernel reports as `-1`
+fn expires(now: Instant, lifetime: i32) -> Option<Instant> {
+    let seconds = u64::try_from(lifetime).ok()?;
+    now.checked_add(Duration::from_secs(seconds))
+}
+/// Get whether a [`RouteMessage`] is an ipv6 route and its priority when it is a unicast default route.
+///
+/// A route without a priority has priority 0.
+fn parse_default_route_header(route: &RouteMessage) -> Option<(bool, u32)> {
+    let ipv6 = match u16::from(route.header.address_family) {
+        AF_INET => false,
+        AF_INET6 => true,
+        _ => return None,
+    };
+    if route.header.destination_prefix_length != 0 || route.header.kind != constants::RTN_UNICAST {
+        return None;
+    }
+    let priority = route
+        .nlas
+        .iter()
+        .find_map(|nla| {
+            if let nlas::route::Nla::Priority(priority) = *nla {
+                Some(priority)
+            } else {
+                None
+            }
+        })
+        .unwrap_or_default();
+    Some((ipv6, priority))
 }
 /// Extract useful information from a [`RouteMessage`].
 ///
-/// Has a valid result when the message has an Output Interface, Gateway, and priority.
-fn parse_default_route(route: &RouteMessage) -> Option<RouteInfo> {
+/// Has a result for every next hop when the message is a unicast default route.
+/// A route that refers to a nexthop object without including its next hops in the message has no result, those are resolved by [`NextHops`].
+fn parse_default_route(route: &RouteMessage) -> Vec<RouteInfo> {
+    let (ipv6, priority) = match parse_default_route_header(route) {
+        Some(header) => header,
+        None => return Vec::new(),
+    };
     let oif = route.nlas.iter().find_map(|nla| {
         if let nlas::route::Nla::Oif(oif) = *nla {
             Some(oif)
         } else {
             None
         }
-    })?;
+    });
     let gateway = route.nlas.iter().find_map(|nla| {
         if let nlas::route::Nla::Gateway(ref address) = *nla {
-            Some(address)
+            parse_ip_address(address)
+        } else if let nlas::route::Nla::Via(ref via) = *nla {
+            via.get(2..).and_then(parse_ip_address)
         } else {
             None
         }
-    })?;
-    let priority = route.nlas.iter().find_map(|nla| {
-        if let nlas::route::Nla::Priority(priority) = *nla {
-            Some(priority)
+    });
+    let multipath = route.nlas.iter().find_map(|nla| {
+        if let nlas::route::Nla::MultiPath(ref encoded_next_hops) = *nla {
+            Some(encoded_next_hops)
+        } else {
+            None
+        }
+    });
+    let route_next_hops = match (oif, multipath) {
+        (Some(index), _) => vec![(index, gateway)],
+        (None, Some(multipath_next_hops)) => parse_next_hops(multipath_next_hops),
+        (None, None) => Vec::new(),
+    };
+    route_infos(ipv6, priority, route_next_hops)
+}
+/// Convert the output interface and gateway of every next hop of a default route to a [`RouteInfo`].
+///
+/// A next hop without a gateway of the ip type of the route, like on a point-to-point link or via a gateway of another ip type, has no gateway.
+fn route_infos(ipv6: bool, priority: u32, next_hops: Vec<(u32, Option<IpAddr>)>) -> Vec<RouteInfo> {
+    next_hops
+        .into_iter()
+        .map(|(index, next_hop_gateway)| {
+            let route_gateway = match (ipv6, next_hop_gateway) {
+                (false, Some(IpAddr::V4(ipv4_address))) => RouteGateway::V4(Some(ipv4_address)),
+                (false, _) => RouteGateway::V4(None),
+                (true, Some(IpAddr::V6(ipv6_address))) => RouteGateway::V6(Some(ipv6_address)),
+                (true, _) => RouteGateway::V6(None),
+            };
+            (index, route_gateway, priority)
+        })
+        .collect()
+}
+/// Extract the output interface and gateway of every next hop of a multipath route.
+// netlink messages are in the byte order of the host
+#[allow(clippy::host_endian_bytes)]
+fn parse_next_hops(mut next_hops: &[u8]) -> Vec<(u32, Option<IpAddr>)> {
+    let mut parsed = Vec::new();
+    // every next hop is a header of length, flags, hops and output interface followed by its attributes
+    while let (Some(length), Some(index)) = (
+        next_hops
+            .get(..2)
+            .and_then(|bytes| <[u8; 2]>::try_from(bytes).ok()),
+        next_hops
+            .get(4..8)
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok()),
+    ) {
+        let next_hop_length = usize::from(u16::from_ne_bytes(length));
+        let attributes = match next_hops.get(8..next_hop_length) {
+            Some(attributes) => attributes,
+            None => break,
+        };
+        let gateway = NlasIterator::new(attributes)
+            .filter_map(Result::ok)
+            .find_map(|nla| match nla.kind() {
+                constants::RTA_GATEWAY => parse_ip_address(nla.value()),
+                constants::RTA_VIA => nla.value().get(2..).and_then(parse_ip_address),
+                _ => None,
+            });
+        parsed.push((u32::from_ne_bytes(index), gateway));
+        // next hops are aligned to 4 bytes
+        let aligned = next_hop_length.saturating_add(3) & !3;
+        next_hops = next_hops.get(aligned..).unwrap_or_default();
+    }
+    parsed
+}
+/// Get the [`Reference`] of a unicast default route to a nexthop object when the message doesn't include its next hops.
+///
+/// The kernel only includes the next hops of a nexthop object in route messages when `nexthop_compat_mode` is enabled.
+fn next_hop_reference(route: &RouteMessage) -> Option<Reference> {
+    let (ipv6, priority) = parse_default_route_header(route)?;
+    let includes_next_hops = route.nlas.iter().any(|nla| {
+        matches!(
+            *nla,
+            nlas::route::Nla::Oif(_) | nlas::route::Nla::MultiPath(_)
+        )
+    });
+    if includes_next_hops {
+        return None;
+    }
+    let id = route.nlas.iter().find_map(|nla| {
+        if let nlas::route::Nla::Other(ref attribute) = *nla {
+            (attribute.kind() == RTA_NH_ID).then_some(())?;
+            let mut value = vec![0; attribute.value_len()];
+            attribute.emit_value(&mut value);
+            parse_u32(&value)
         } else {
             None
         }
     })?;
-    let ip_address = match u16::from(route.header.address_family) {
-        AF_INET => Some(IpAddr::V4(Ipv4Addr::from(
-            vec_to_array(gateway.clone()).ok()?,
-        ))),
-        AF_INET6 => Some(IpAddr::V6(Ipv6Addr::from(
-            vec_to_array(gateway.clone()).ok()?,
-        ))),
-        _ => None,
-    }?;
-    Some((oif, ip_address, priority))
+    Some((ipv6, id, priority))
 }
-
+/// Replaces the default routes previous with current in state
+///
+/// # Returns
+///
+/// The last [Event] of the default routes that were removed or added
+fn replace_default_routes(
+    state: &mut Interfaces,
+    previous: &[RouteInfo],
+    current: &[RouteInfo],
+) -> Option<Event> {
+    let removed = previous
+        .iter()
+        .filter(|&route| !current.contains(route))
+        .fold(None, |cause, &route| {
+            state.remove_default_route(route).or(cause)
+        });
+    current
+        .iter()
+        .filter(|&route| !previous.contains(route))
+        .fold(removed, |cause, &route| {
+            state.add_default_route(route).or(cause)
+        })
+}
+/// A nexthop object that routes can refer to by its id.
 #[derive(Debug)]
-/// Error enum for things that are not actual errors
-enum ConnectivityError {
-    /// Forward for [NetlinkPayload::Overrun]
-    Overrun(Vec<u8>),
-}
-impl Display for ConnectivityError {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match *self {
-            Self::Overrun(_) => {
-                write!(f, "An rtnetlink overrun occurred")?;
+enum NextHop {
+    /// A single next hop through the output interface with the gateway if it has one
+    Single(u32, Option<IpAddr>),
+    /// A group of the nexthop objects with the contained ids
+    Group(Vec<u32>),
+}
+/// Records the nexthop objects of a network namespace and the default routes that refer to them without including their next hops.
+///
+/// rtnetlink can't parse nexthop messages, so the nexthop objects are requested and their changes are received over a socket of their own.
+struct NextHops {
+    /// The socket the nexthop objects are requested over, which receives their changes when it joined the multicast group
+    socket: Socket,
+    /// The nexthop objects by their id
+    known: HashMap<u32, NextHop>,
+    /// The default routes that refer to a nexthop object with what they resolved to
+    routes: HashMap<Reference, Vec<RouteInfo>>,
+}
+impl NextHops {
+    /// Create a new [`NextHops`] instance in namespace that doesn't know any nexthop objects yet and receives their changes when subscribe is set
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if namespace couldn't be entered, the socket couldn't be created or the membership couldn't be added.
+    fn new(namespace: Option<&NetworkNamespace>, subscribe: bool) -> Result<Self, Error> {
+        let mut socket =
+            in_namespace(namespace, || Socket::new(NETLINK_ROUTE))?.map_err(Error::Socket)?;
+        if subscribe {
+            match socket.socket_mut().add_membership(RTNLGRP_NEXTHOP) {
+                // kernels without nexthop objects don't have their multicast group
+                Err(error) if error.raw_os_error() == Some(libc::EINVAL) => {
+                    debug!("nexthop objects unsupported {}", error);
+                }
+                result => result.map_err(|source| Error::Membership {
+                    group: RTNLGRP_NEXTHOP,
+                    source,
+                })?,
+            }
+        }
+        Ok(Self {
+            socket,
+            known: HashMap::new(),
+            routes: HashMap::new(),
+        })
+    }
+
+    /// Retrieves the nexthop objects from the system.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request failed.
+    async fn refresh(&mut self) -> Result<(), Error> {
+        debug!("getting nexthop objects");
+        let mut request = vec![0; NETLINK_HEADER_LEN + NEXTHOP_HEADER_LEN];
+        let length = u32::try_from(request.len()).map_err(|error| Error::Dump(Box::new(error)))?;
+        let mut header = NetlinkBuffer::new(&mut request);
+        header.set_length(length);
+        header.set_message_type(RTM_GETNEXTHOP);
+        header.set_flags(NLM_F_REQUEST_DUMP);
+        header.set_sequence_number(1);
+        self.socket
+            .send(&request)
+            .await
+            .map_err(|error| Error::Dump(Box::new(error)))?;
+        loop {
+            let (response, _) = self
+                .socket
+                .recv_from_full()
+                .await
+                .map_err(|error| Error::Dump(Box::new(error)))?;
+            if self.receive(&response)? {
+                return Ok(());
             }
         }
+    }
 
-        Ok(())
+    /// Completes with the next datagram of changes to nexthop objects
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the socket couldn't be read.
+    async fn changes(&self) -> io::Result<Vec<u8>> {
+        self.socket
+            .recv_from_full()
+            .await
+            .map(|(datagram, _)| datagram)
     }
+
+    /// Records the nexthop objects in the messages of datagram and forgets the removed ones
+    ///
+    /// # Returns
+    ///
+    /// Whether datagram ends a request.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if datagram is invalid or the system answered a request with an error.
+    fn receive(&mut self, datagram: &[u8]) -> Result<bool, Error> {
+        let mut messages = datagram;
+        while !messages.is_empty() {
+            let message = NetlinkBuffer::new_checked(messages)
+                .map_err(|error| Error::Dump(error.to_string().into()))?;
+            match message.message_type() {
+                NLMSG_DONE => return Ok(true),
+                NLMSG_ERROR => {
+                    return match message.payload().get(..4).and_then(parse_i32) {
+                        Some(0) => Ok(true),
+                        Some(error) => Err(Error::Netlink(io::Error::from_raw_os_error(
+                            error.saturating_neg(),
+                        ))),
+                        None => Err(Error::Dump("invalid netlink error message".into())),
+                    };
+                }
+                RTM_NEWNEXTHOP => self.record(message.payload()),
+                RTM_DELNEXTHOP => self.forget(message.payload()),
+                _ => {}
+            }
+            // messages are aligned to 4 bytes
+            let aligned = usize::try_from(message.length())
+                .unwrap_or(usize::MAX)
+                .saturating_add(3)
+                & !3;
+            messages = messages.get(aligned..).unwrap_or_default();
+        }
+        Ok(false)
+    }
+
+    /// Get the id in the payload of a nexthop message
+    fn id(payload: &[u8]) -> Option<u32> {
+        let attributes = payload.get(NEXTHOP_HEADER_LEN..).unwrap_or_default();
+        NlasIterator::new(attributes)
+            .filter_map(Result::ok)
+            .find_map(|attribute| {
+                (attribute.kind() == NHA_ID)
+                    .then(|| parse_u32(attribute.value()))
+                    .flatten()
+            })
+    }
+
+    /// Records the nexthop object in the payload of a nexthop message
+    fn record(&mut self, payload: &[u8]) {
+        let mut index = None;
+        let mut gateway = None;
+        let mut members = None;
+        let attributes = payload.get(NEXTHOP_HEADER_LEN..).unwrap_or_default();
+        for attribute in NlasIterator::new(attributes).filter_map(Result::ok) {
+            match attribute.kind() {
+                NHA_OIF => index = parse_u32(attribute.value()),
+                NHA_GATEWAY => gateway = parse_ip_address(attribute.value()),
+                NHA_GROUP => {
+                    members = Some(
+                        attribute
+                            .value()
+                            .chunks_exact(NEXTHOP_GROUP_MEMBER_LEN)
+                            .filter_map(|member| member.get(..4).and_then(parse_u32))
+                            .collect(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        let id = match Self::id(payload) {
+            Some(id) => id,
+            None => return,
+        };
+        // a blackhole nexthop object has neither an output interface nor members, so routes through it have no next hops
+        let next_hop = match (members, index) {
+            (Some(group), _) => NextHop::Group(group),
+            (None, Some(output_interface)) => NextHop::Single(output_interface, gateway),
+            (None, None) => NextHop::Group(Vec::new()),
+        };
+        debug!("nexthop object {} is {:?}", id, next_hop);
+        self.known.insert(id, next_hop);
+    }
+
+    /// Forgets the nexthop object in the payload of a removed nexthop message
+    fn forget(&mut self, payload: &[u8]) {
+        if let Some(id) = Self::id(payload) {
+            debug!("nexthop object {} removed", id);
+            self.known.remove(&id);
+        }
+    }
+
+    /// Whether the nexthop object reference refers to is known
+    fn knows(&self, reference: Reference) -> bool {
+        let (_, id, _) = reference;
+        self.known.contains_key(&id)
+    }
+
+    /// Get a [`RouteInfo`] for every next hop of the nexthop object reference refers to
+    fn resolve(&self, reference: Reference) -> Vec<RouteInfo> {
+        let (ipv6, id, priority) = reference;
+        let next_hops = self
+            .known
+            .get(&id)
+            .map_or_else(Vec::new, |next_hop| match *next_hop {
+                NextHop::Single(index, gateway) => vec![(index, gateway)],
+                // groups only have single nexthop objects as members
+                NextHop::Group(ref members) => members
+                    .iter()
+                    .filter_map(|member| {
+                        if let Some(&NextHop::Single(index, gateway)) = self.known.get(member) {
+                            Some((index, gateway))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect(),
+            });
+        route_infos(ipv6, priority, next_hops)
+    }
+
+    /// Adds the default route of reference to state with the next hops of the nexthop object it refers to
+    ///
+    /// # Returns
+    ///
+    /// The last [Event] of the default routes that changed
+    fn add_route(&mut self, reference: Reference, state: &mut Interfaces) -> Option<Event> {
+        let current = self.resolve(reference);
+        let previous = self.routes.insert(reference, current.clone());
+        replace_default_routes(state, &previous.unwrap_or_default(), &current)
+    }
+
+    /// Removes the default route of reference from state with the next hops it was added with
+    ///
+    /// # Returns
+    ///
+    /// The last [Event] of the default routes that changed
+    fn remove_route(&mut self, reference: Reference, state: &mut Interfaces) -> Option<Event> {
+        let previous = self.routes.remove(&reference)?;
+        replace_default_routes(state, &previous, &[])
+    }
+
+    /// Forgets the default routes that refer to a nexthop object, like when the state is retrieved again
+    fn forget_routes(&mut self) {
+        self.routes.clear();
+    }
+
+    /// Resolves all default routes that refer to a nexthop object again after the nexthop objects changed
+    ///
+    /// The kernel removes the routes that refer to a removed nexthop object without notifying it, so those routes are removed as well.
+    ///
+    /// # Returns
+    ///
+    /// The last [Event] of the default routes that changed
+    fn update_routes(&mut self, state: &mut Interfaces) -> Option<Event> {
+        let previous_routes = mem::take(&mut self.routes);
+        previous_routes
+            .into_iter()
+            .fold(None, |cause, (reference, previous)| {
+                if self.knows(reference) {
+                    let current = self.resolve(reference);
+                    let changed = replace_default_routes(state, &previous, &current);
+                    self.routes.insert(reference, current);
+                    changed.or(cause)
+                } else {
+                    replace_default_routes(state, &previous, &[]).or(cause)
+                }
+            })
+    }
+}
+/// Convert the bytes of a number in the byte order of the host to a [u32].
+// netlink messages are in the byte order of the host
+#[allow(clippy::host_endian_bytes)]
+fn parse_u32(bytes: &[u8]) -> Option<u32> {
+    <[u8; 4]>::try_from(bytes).ok().map(u32::from_ne_bytes)
+}
+/// Convert the bytes of a number in the byte order of the host to an [i32].
+// netlink messages are in the byte order of the host
+#[allow(clippy::host_endian_bytes)]
+fn parse_i32(bytes: &[u8]) -> Option<i32> {
+    <[u8; 4]>::try_from(bytes).ok().map(i32::from_ne_bytes)
+}
+/// Convert the bytes of an address to an [`IpAddr`] by their length.
+fn parse_ip_address(address: &[u8]) -> Option<IpAddr> {
+    <[u8; 4]>::try_from(address)
+        .map(|ipv4_address| IpAddr::V4(Ipv4Addr::from(ipv4_address)))
+        .or_else(|_error| {
+            <[u8; 16]>::try_from(address)
+                .map(|ipv6_address| IpAddr::V6(Ipv6Addr::from(ipv6_address)))
+        })
+        .ok()
 }
-impl Error for ConnectivityError {}
 
 /// Builds and updates an internal state with a subset of the information provided by rtnetlink.
 ///
-/// From this state the internet connectivity with will be determined and send to tx.
+/// Only the state that passes filter is recorded.
+/// From this state the updates for emitter will be determined and send to its sender.
+/// The initial state is emitted without debouncing when nothing was emitted before, so the state retrieved after a lost connection is only emitted when it changed.
+/// When the socket buffer overruns, the connection ends and no more messages are received.
 ///
-/// This function will compete when the receiving end of tx is dropped.
+/// # Returns
+///
+/// true when the receiving end of the sender is dropped and false when no more rtnetlink messages are received.
 ///
 /// # Errors
 ///
@@ -217,97 +1006,146 @@ impl Error for ConnectivityError {}
 async fn check_internet_connectivity(
     handle: Handle,
     mut messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
-    tx: tokio::sync::mpsc::UnboundedSender<Connectivity>,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+    emitter: &mut Emitter,
+    filter: Filter,
+    next_hops: &mut NextHops,
+) -> Result<bool, Error> {
     debug!("getting initial state");
-    let mut state = Interfaces::new();
-    get_links(&handle, &mut state).await?;
-    get_addresses(&handle, &mut state).await?;
-    get_default_routes(&handle, IpVersion::V4, &mut state).await?;
-    get_default_routes(&handle, IpVersion::V6, &mut state).await?;
+    let mut state = get_state(&handle, filter.clone(), next_hops).await?;
     debug!("got initial state");
 
-    let mut connectivity = state.connectivity();
-    debug!("emit initial connectivity {:?}", connectivity);
-    tx.send(connectivity)?;
+    emitter.running();
+
+    debug!("emit initial state");
+    emitter.update(&state, None)?;
+    emitter.flush_initial()?;
 
     debug!("waiting for rtnetlink messages or transmit channel closed");
-    #[allow(clippy::arithmetic_side_effects, clippy::integer_arithmetic)]
-    while let Some((message, _)) = tokio::select! {
-        biased;
-        _ = tx.closed() => {
-            debug!("transmit channel closed");
-            None
-        },
-        message = messages.next() => {
-            if message.is_none() {
-                debug!("no more rtnetlink messages");
-            }
+    let mut closed = emitter.closed();
+    loop {
+        let next_message = select_biased! {
+            _ = closed => {
+                debug!("transmit channel closed");
+                return Ok(true);
+            },
+            idled = emitter.idle().fuse() => {
+                idled?;
+                continue;
+            },
+            changes = next_hops.changes().fuse() => {
+                let datagram = match changes {
+                    Ok(datagram) => datagram,
+                    Err(error) if error.raw_os_error() == Some(libc::ENOBUFS) => {
+                        warn!("nexthop overrun, resynchronising");
+                        return Ok(false);
+                    }
+                    Err(error) => return Err(Error::Socket(error)),
+                };
+                next_hops.receive(&datagram)?;
+                let cause = next_hops.update_routes(&mut state);
+                emitter.update(&state, cause)?;
+                continue;
+            },
+            message = messages.next() => message,
+        };
+        let (message, _) = if let Some(message) = next_message {
             message
-        },
-    } {
+        } else {
+            debug!("no more rtnetlink messages");
+            return Ok(false);
+        };
+
         #[allow(clippy::wildcard_enum_match_arm)]
-        match message.payload {
-            NetlinkPayload::Error(e) => {
-                return Err(Box::new(rtnetlink::Error::NetlinkError(e)));
-            }
-            NetlinkPayload::Overrun(e) => {
-                return Err(Box::new(ConnectivityError::Overrun(e)));
+        let cause = match message.payload {
+            NetlinkPayload::Overrun(_) => {
+                warn!("rtnetlink overrun, resynchronising");
+                state = get_state(&handle, filter.clone(), next_hops).await?;
+                emitter.resynced();
+                None
             }
             NetlinkPayload::InnerMessage(inner_message) => match inner_message {
                 RtnlMessage::NewLink(ref link) => {
-                    state.add_link(parse_link(link));
+                    parse_link(link).and_then(|parsed_link| state.add_link(parsed_link))
                 }
                 RtnlMessage::DelLink(ref link) => {
-                    state.remove_link(parse_link(link));
-                }
-                RtnlMessage::NewAddress(ref address) => {
-                    if let Some(parsed_address) = parse_address(address) {
-                        state.add_address(parsed_address);
-                    }
-                }
-                RtnlMessage::DelAddress(ref address) => {
-                    if let Some(parsed_address) = parse_address(address) {
-                        state.remove_address(parsed_address);
-                    }
+                    parse_link(link).and_then(|parsed_link| state.remove_link(&parsed_link))
                 }
+                RtnlMessage::NewAddress(ref address) => parse_address(address)
+                    .and_then(|parsed_address| state.add_address(&parsed_address)),
+                RtnlMessage::DelAddress(ref address) => parse_address(address)
+                    .and_then(|parsed_address| state.remove_address(&parsed_address)),
                 RtnlMessage::NewRoute(ref route) => {
-                    if let Some(parsed_route) = parse_default_route(route) {
-                        state.add_default_route(parsed_route);
+                    if let Some(reference) = next_hop_reference(route) {
+                        // the change of the nexthop object can still be on its way
+                        if !next_hops.knows(reference) {
+                            next_hops.refresh().await?;
+                        }
+                        next_hops.add_route(reference, &mut state)
+                    } else {
+                        parse_default_route(route)
+                            .into_iter()
+                            .fold(None, |cause, parsed_route| {
+                                state.add_default_route(parsed_route).or(cause)
+                            })
                     }
                 }
+                // the nexthop object of the route can be removed already, so it is removed with what it was added
                 RtnlMessage::DelRoute(ref route) => {
-                    if let Some(parsed_route) = parse_default_route(route) {
-                        state.remove_default_route(parsed_route);
+                    if let Some(reference) = next_hop_reference(route) {
+                        next_hops.remove_route(reference, &mut state)
+                    } else {
+                        parse_default_route(route)
+                            .into_iter()
+                            .fold(None, |cause, parsed_route| {
+                                state.remove_default_route(parsed_route).or(cause)
+                            })
                     }
                 }
-                _ => {}
+                _ => None,
             },
-            _ => {}
-        }
+            _ => None,
+        };
 
-        if diff_assign(&mut connectivity, state.connectivity()) {
-            debug!("emit updated connectivity {:?}", connectivity);
-            tx.send(connectivity)?;
-        }
+        emitter.update(&state, cause)?;
     }
-
-    Ok(())
 }
 
+/// Gets the complete state that passes filter from rtnetlink.
+///
+/// # Errors
+///
+/// This function will return an error if any of the underlying requests has an error.
+async fn get_state(
+    handle: &Handle,
+    filter: Filter,
+    next_hops: &mut NextHops,
+) -> Result<Interfaces, Error> {
+    let ipv4 = filter.ipv4;
+    let ipv6 = filter.ipv6;
+    let mut state = Interfaces::new(filter);
+    next_hops.forget_routes();
+    get_links(handle, &mut state).await?;
+    get_addresses(handle, &mut state).await?;
+    if ipv4 {
+        get_default_routes(handle, IpVersion::V4, &mut state, next_hops).await?;
+    }
+    if ipv6 {
+        get_default_routes(handle, IpVersion::V6, &mut state, next_hops).await?;
+    }
+    Ok(state)
+}
 /// Gets all interfaces from rtnetlink ignoring the loopback interfaces and records them in the [state](Interfaces).
 ///
 /// # Errors
 ///
 /// This function will return an error if the underlying request has an error.
-async fn get_links(
-    handle: &Handle,
-    state: &mut Interfaces,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn get_links(handle: &Handle, state: &mut Interfaces) -> Result<(), Error> {
     let mut links = handle.link().get().execute();
 
-    while let Some(ref link) = links.try_next().await? {
-        state.add_link(parse_link(link));
+    while let Some(ref link) = links.try_next().await.map_err(dump_error)? {
+        if let Some(parsed_link) = parse_link(link) {
+            state.add_link(parsed_link);
+        }
     }
 
     Ok(())
@@ -317,15 +1155,12 @@ async fn get_links(
 /// # Errors
 ///
 /// This function will return an error if the underlying request has an error.
-async fn get_addresses(
-    handle: &Handle,
-    state: &mut Interfaces,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn get_addresses(handle: &Handle, state: &mut Interfaces) -> Result<(), Error> {
     let mut addresses = handle.address().get().execute();
 
-    while let Some(ref address) = addresses.try_next().await? {
+    while let Some(ref address) = addresses.try_next().await.map_err(dump_error)? {
         if let Some(parsed_address) = parse_address(address) {
-            state.add_address(parsed_address);
+            state.add_address(&parsed_address);
         }
     }
 
@@ -340,14 +1175,697 @@ async fn get_default_routes(
     handle: &Handle,
     ip_version: IpVersion,
     state: &mut Interfaces,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+    next_hops: &mut NextHops,
+) -> Result<(), Error> {
     let mut routes = handle.route().get(ip_version).execute();
 
-    while let Some(ref route) = routes.try_next().await? {
-        if let Some(parsed_route) = parse_default_route(route) {
-            state.add_default_route(parsed_route);
+    let mut refreshed = false;
+    while let Some(ref route) = routes.try_next().await.map_err(dump_error)? {
+        if let Some(reference) = next_hop_reference(route) {
+            if !refreshed && !next_hops.knows(reference) {
+                next_hops.refresh().await?;
+                refreshed = true;
+            }
+            next_hops.add_route(reference, state);
+        } else {
+            for parsed_route in parse_default_route(route) {
+                state.add_default_route(parsed_route);
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddressScope, ConnectivityMonitor, ConnectivityState, Event, Probe};
+    use rtnetlink::packet::traits::Emitable;
+    use std::{sync::Arc, thread};
+
+    /// The index of the interface the fixtures are assigned to
+    const INDEX: u32 = 2;
+
+    /// Create an address message for address with flags and the preferred and valid lifetimes in seconds
+    fn address_message(address: IpAddr, flags: u32, preferred: i32, valid: i32) -> AddressMessage {
+        let (family, bytes) = match address {
+            IpAddr::V4(ipv4_address) => (AF_INET, ipv4_address.octets().to_vec()),
+            IpAddr::V6(ipv6_address) => (AF_INET6, ipv6_address.octets().to_vec()),
+        };
+        let cache_info = CacheInfo {
+            ifa_preferred: preferred,
+            ifa_valid: valid,
+            cstamp: 0,
+            tstamp: 0,
+        };
+        let mut cache_info_bytes = vec![0; cache_info.buffer_len()];
+        cache_info.emit(&mut cache_info_bytes);
+        let mut message = AddressMessage::default();
+        message.header.family = u8::try_from(family).unwrap();
+        message.header.prefix_len = 24;
+        message.header.scope = constants::RT_SCOPE_UNIVERSE;
+        message.header.index = INDEX;
+        message.nlas = vec![
+            nlas::address::Nla::Address(bytes),
+            nlas::address::Nla::Flags(flags),
+            nlas::address::Nla::CacheInfo(cache_info_bytes),
+        ];
+        message
+    }
+
+    /// Create the state for filter with the fixture interface up
+    fn interfaces(permanent_addresses: bool) -> Interfaces {
+        let mut state = Interfaces::new(Filter {
+            permanent_addresses,
+            ..Filter::default()
+        });
+        state.add_link(LinkInfo {
+            index: INDEX,
+            name: Some("eth0".to_owned()),
+            loopback: false,
+            state: LinkState::Up,
+            mac: None,
+            mtu: Some(1500),
+            kind: None,
+            bridge_port: false,
+        });
+        state
+    }
+
+    /// Asserts that lifetime expires lifetime seconds after a moment between before and now
+    fn assert_expires(until: Option<Instant>, before: Instant, lifetime: u64) {
+        let until = until.expect("the lifetime is finite");
+        let lifetime = Duration::from_secs(lifetime);
+        assert!(until >= before + lifetime && until <= Instant::now() + lifetime);
+    }
+
+    #[test]
+    fn static_address_is_permanent() {
+        let address = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10));
+        let message = address_message(address, constants::IFA_F_PERMANENT, -1, -1);
+
+        let parsed = parse_address(&message).unwrap();
+        assert_eq!(parsed.index, INDEX);
+        assert_eq!(parsed.address, address);
+        assert_eq!(parsed.state, AddressState::Preferred);
+        assert_eq!(parsed.scope, Scope::Global);
+        assert_eq!(parsed.preferred_until, None);
+        assert_eq!(parsed.valid_until, None);
+        assert!(parsed.permanent);
+
+        let mut counted = interfaces(true);
+        assert_eq!(
+            counted.add_address(&parsed),
+            Some(Event::AddressAdded {
+                index: INDEX,
+                name: Some("eth0".to_owned()),
+                address,
+            })
+        );
+        assert_eq!(counted.connectivity().ipv4, ConnectivityState::Network);
+
+        let mut ignored = interfaces(false);
+        assert_eq!(ignored.add_address(&parsed), None);
+        assert_eq!(ignored.connectivity().ipv4, ConnectivityState::None);
+        assert!(ignored.snapshots()[0].ipv4.addresses.is_empty());
+    }
+
+    #[test]
+    fn address_that_became_permanent_is_removed() {
+        let address = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let dynamic = parse_address(&address_message(address, 0, 1800, 3600)).unwrap();
+        let permanent = parse_address(&address_message(
+            address,
+            constants::IFA_F_PERMANENT,
+            -1,
+            -1,
+        ))
+        .unwrap();
+
+        let mut state = interfaces(false);
+        assert!(state.add_address(&dynamic).is_some());
+        assert_eq!(state.connectivity().ipv4, ConnectivityState::Network);
+        assert_eq!(
+            state.add_address(&permanent),
+            Some(Event::AddressRemoved {
+                index: INDEX,
+                name: Some("eth0".to_owned()),
+                address,
+            })
+        );
+        assert_eq!(state.connectivity().ipv4, ConnectivityState::None);
+        assert!(state.snapshots()[0].ipv4.addresses.is_empty());
+        assert_eq!(state.add_address(&permanent), None);
+    }
+
+    #[test]
+    fn dhcp_address_expires() {
+        let address = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let message = address_message(address, 0, 1800, 3600);
+
+        let before = Instant::now();
+        let parsed = parse_address(&message).unwrap();
+        assert_eq!(parsed.state, AddressState::Preferred);
+        assert_expires(parsed.preferred_until, before, 1800);
+        assert_expires(parsed.valid_until, before, 3600);
+        assert!(!parsed.permanent);
+
+        for permanent_addresses in [true, false] {
+            let mut state = interfaces(permanent_addresses);
+            assert_eq!(
+                state.add_address(&parsed),
+                Some(Event::AddressAdded {
+                    index: INDEX,
+                    name: Some("eth0".to_owned()),
+                    address,
+                })
+            );
+            assert_eq!(state.connectivity().ipv4, ConnectivityState::Network);
+            let snapshot = state.snapshots()[0].ipv4.addresses[0];
+            assert_eq!(snapshot.scope, AddressScope::Private);
+            assert_eq!(snapshot.valid_until, parsed.valid_until);
+            assert!(snapshot.usable);
+        }
+    }
+
+    #[test]
+    fn slaac_address_is_usable_once_preferred() {
+        let ipv6_address = Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0x5054, 0xff, 0xfe12, 0x3456);
+        let address = IpAddr::V6(ipv6_address);
+        let tentative = address_message(address, constants::IFA_F_TENTATIVE, 14400, 86400);
+        let preferred = address_message(address, 0, 14400, 86400);
+
+        let parsed_tentative = parse_address(&tentative).unwrap();
+        assert_eq!(parsed_tentative.state, AddressState::Tentative);
+        assert!(!parsed_tentative.permanent);
+        let parsed_preferred = parse_address(&preferred).unwrap();
+        assert_eq!(parsed_preferred.state, AddressState::Preferred);
+        assert!(!parsed_preferred.permanent);
+
+        for permanent_addresses in [true, false] {
+            let mut state = interfaces(permanent_addresses);
+            assert_eq!(state.add_address(&parsed_tentative), None);
+            assert_eq!(state.connectivity().ipv6, ConnectivityState::None);
+            assert!(!state.snapshots()[0].ipv6.addresses[0].usable);
+
+            assert_eq!(
+                state.add_address(&parsed_preferred),
+                Some(Event::AddressAdded {
+                    index: INDEX,
+                    name: Some("eth0".to_owned()),
+                    address,
+                })
+            );
+            assert_eq!(state.connectivity().ipv6, ConnectivityState::Network);
+            let snapshot = state.snapshots()[0].ipv6.addresses[0];
+            assert_eq!(snapshot.address, ipv6_address);
+            assert_eq!(snapshot.scope, AddressScope::Global);
+            assert!(snapshot.usable);
+        }
+    }
+
+    /// Encode attributes as the kind and value of netlink attributes
+    // netlink messages are in the byte order of the host
+    #[allow(clippy::host_endian_bytes)]
+    fn encode_attributes(attributes: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for &(kind, value) in attributes {
+            let length = u16::try_from(value.len() + 4).unwrap();
+            encoded.extend_from_slice(&length.to_ne_bytes());
+            encoded.extend_from_slice(&kind.to_ne_bytes());
+            encoded.extend_from_slice(value);
+            encoded.resize((encoded.len() + 3) & !3, 0);
+        }
+        encoded
+    }
+
+    /// Create the payload of a nexthop message with attributes
+    fn next_hop_payload(attributes: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut payload = vec![0; NEXTHOP_HEADER_LEN];
+        payload.extend(encode_attributes(attributes));
+        payload
+    }
+
+    /// Create a [`NextHops`] instance that isn't subscribed to changes
+    fn next_hops() -> NextHops {
+        // the socket is created in a runtime
+        runtime::block_on(async { NextHops::new(None, false) })
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Create the payload of a nexthop message for the nexthop object id through the fixture interface via gateway
+    // netlink messages are in the byte order of the host
+    #[allow(clippy::host_endian_bytes)]
+    fn single_next_hop(id: u32, gateway: [u8; 4]) -> Vec<u8> {
+        next_hop_payload(&[
+            (NHA_ID, &id.to_ne_bytes()),
+            (NHA_OIF, &INDEX.to_ne_bytes()),
+            (NHA_GATEWAY, &gateway),
+        ])
+    }
+
+    /// Create an ipv4 default route message with priority 5 that refers to the nexthop object id
+    // netlink messages are in the byte order of the host
+    #[allow(clippy::host_endian_bytes)]
+    fn next_hop_route(id: u32) -> RouteMessage {
+        let mut message = RouteMessage::default();
+        message.header.address_family = u8::try_from(AF_INET).unwrap();
+        message.header.kind = constants::RTN_UNICAST;
+        message.nlas = vec![
+            nlas::route::Nla::Other(nlas::DefaultNla::new(RTA_NH_ID, id.to_ne_bytes().to_vec())),
+            nlas::route::Nla::Priority(5),
+        ];
+        message
+    }
+
+    #[test]
+    #[allow(clippy::host_endian_bytes)]
+    fn nexthop_object_routes_are_resolved() {
+        let mut next_hops = next_hops();
+        next_hops.record(&single_next_hop(1, [10, 0, 0, 1]));
+        next_hops.record(&next_hop_payload(&[
+            (NHA_ID, &2_u32.to_ne_bytes()),
+            (NHA_OIF, &3_u32.to_ne_bytes()),
+        ]));
+        // every group member is an id followed by a weight and reserved bytes
+        let members = [1_u32.to_ne_bytes(), [0; 4], 2_u32.to_ne_bytes(), [0; 4]].concat();
+        next_hops.record(&next_hop_payload(&[
+            (NHA_ID, &3_u32.to_ne_bytes()),
+            (NHA_GROUP, &members),
+        ]));
+        // a blackhole has no next hops
+        next_hops.record(&next_hop_payload(&[
+            (NHA_ID, &4_u32.to_ne_bytes()),
+            (4, &[]),
+        ]));
+
+        let reference = |id: u32| next_hop_reference(&next_hop_route(id)).unwrap();
+        let gateway = RouteGateway::V4(Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(reference(1), (false, 1, 5));
+        assert_eq!(next_hops.resolve(reference(1)), vec![(INDEX, gateway, 5)]);
+        assert_eq!(
+            next_hops.resolve(reference(3)),
+            vec![(INDEX, gateway, 5), (3, RouteGateway::V4(None), 5)]
+        );
+        assert_eq!(next_hops.resolve(reference(4)), vec![]);
+        assert!(!next_hops.knows(reference(5)));
+        assert_eq!(next_hops.resolve(reference(5)), vec![]);
+        assert_eq!(parse_default_route(&next_hop_route(1)), vec![]);
+
+        // the next hops in the message take precedence over the nexthop object
+        let mut compatible = next_hop_route(1);
+        compatible.nlas.push(nlas::route::Nla::Oif(7));
+        assert_eq!(next_hop_reference(&compatible), None);
+        assert_eq!(
+            parse_default_route(&compatible),
+            vec![(7, RouteGateway::V4(None), 5)]
+        );
+    }
+
+    #[test]
+    fn nexthop_object_changes_update_the_routes() {
+        let mut next_hops = next_hops();
+        let mut state = interfaces(true);
+        let gateways = |state: &Interfaces| {
+            state.snapshots()[0]
+                .ipv4
+                .gateways
+                .iter()
+                .map(|gateway| gateway.address)
+                .collect::<Vec<_>>()
+        };
+        next_hops.record(&single_next_hop(1, [10, 0, 0, 1]));
+        let reference = next_hop_reference(&next_hop_route(1)).unwrap();
+        assert!(next_hops.add_route(reference, &mut state).is_some());
+        assert_eq!(gateways(&state), vec![Some(Ipv4Addr::new(10, 0, 0, 1))]);
+
+        // like ip nexthop replace
+        next_hops.record(&single_next_hop(1, [10, 0, 0, 2]));
+        assert!(next_hops.update_routes(&mut state).is_some());
+        assert_eq!(gateways(&state), vec![Some(Ipv4Addr::new(10, 0, 0, 2))]);
+        assert!(next_hops.update_routes(&mut state).is_none());
+
+        // the kernel removes the route with the nexthop object without a message for the route
+        next_hops.forget(&single_next_hop(1, [10, 0, 0, 2]));
+        assert!(next_hops.update_routes(&mut state).is_some());
+        assert!(gateways(&state).is_empty());
+        assert!(next_hops.remove_route(reference, &mut state).is_none());
+    }
+
+    #[test]
+    fn optimistic_addresses_are_usable_unlike_deprecated_ones() {
+        let ipv6_address = Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 0x10);
+        let address = IpAddr::V6(ipv6_address);
+        let flags = [
+            (
+                constants::IFA_F_TENTATIVE | constants::IFA_F_OPTIMISTIC,
+                AddressState::Optimistic,
+                true,
+            ),
+            (constants::IFA_F_TENTATIVE, AddressState::Tentative, false),
+            (constants::IFA_F_DEPRECATED, AddressState::Deprecated, false),
+            (
+                constants::IFA_F_TENTATIVE | constants::IFA_F_DADFAILED,
+                AddressState::Duplicate,
+                false,
+            ),
+        ];
+        for (flag, expected_state, expected_usable) in flags {
+            let parsed = parse_address(&address_message(address, flag, 0, 86400)).unwrap();
+            assert_eq!(parsed.state, expected_state);
+
+            let mut state = interfaces(true);
+            state.add_address(&parsed);
+            let snapshot = state.snapshots()[0].ipv6.addresses[0];
+            assert_eq!(snapshot.usable, expected_usable, "{expected_state:?}");
+            let expected_connectivity = if expected_usable {
+                ConnectivityState::Network
+            } else {
+                ConnectivityState::None
+            };
+            assert_eq!(state.connectivity().ipv6, expected_connectivity);
+        }
+    }
+
+    /// Create a link message with flags and operational state
+    fn link_message(flags: u32, operational: Option<nlas::link::State>) -> LinkMessage {
+        let mut message = LinkMessage::default();
+        message.header.index = INDEX;
+        message.header.flags = flags;
+        message.nlas = operational
+            .into_iter()
+            .map(nlas::link::Nla::OperState)
+            .collect();
+        message
+    }
+
+    #[test]
+    fn operational_state_decides_the_link_state() {
+        let running = IFF_UP | IFF_LOWER_UP | IFF_RUNNING;
+        let states = [
+            (nlas::link::State::Up, LinkState::Up),
+            (nlas::link::State::Dormant, LinkState::Dormant),
+            (nlas::link::State::Testing, LinkState::Testing),
+            (nlas::link::State::LowerLayerDown, LinkState::LowerLayerDown),
+            (nlas::link::State::Down, LinkState::Down),
+            (nlas::link::State::NotPresent, LinkState::Down),
+        ];
+        for (operational, expected) in states {
+            assert_eq!(
+                link_state(&link_message(running, Some(operational))),
+                expected
+            );
+            // an interface that is administratively down is down in any operational state
+            assert_eq!(
+                link_state(&link_message(running & !IFF_UP, Some(operational))),
+                LinkState::Down
+            );
+        }
+    }
+
+    #[test]
+    fn flags_decide_the_link_state_without_operational_state() {
+        let running = IFF_UP | IFF_LOWER_UP | IFF_RUNNING;
+        let flags = [
+            (running, LinkState::Up),
+            (running | IFF_DORMANT, LinkState::Dormant),
+            (IFF_UP | IFF_LOWER_UP, LinkState::LowerLayerDown),
+            (IFF_UP | IFF_RUNNING, LinkState::LowerLayerDown),
+            (IFF_LOWER_UP | IFF_RUNNING, LinkState::Down),
+        ];
+        for operational in [
+            None,
+            Some(nlas::link::State::Unknown),
+            Some(nlas::link::State::Other(42)),
+        ] {
+            for (link_flags, expected) in flags {
+                assert_eq!(link_state(&link_message(link_flags, operational)), expected);
+            }
+        }
+    }
+
+    /// Create a link message of family for the veth interface v0 which is up and a port of a bridge
+    ///
+    /// Only a message of the bridge address family lacks the link information.
+    fn bridge_port_message(family: u16) -> LinkMessage {
+        let mut message = LinkMessage::default();
+        message.header.interface_family = u8::try_from(family).unwrap();
+        message.header.index = INDEX;
+        message.header.flags = IFF_UP | IFF_LOWER_UP | IFF_RUNNING;
+        message.nlas = vec![
+            nlas::link::Nla::IfName("v0".to_owned()),
+            nlas::link::Nla::OperState(nlas::link::State::Up),
+        ];
+        if family != AF_BRIDGE {
+            message.nlas.push(nlas::link::Nla::Info(vec![
+                nlas::link::Info::Kind(nlas::link::InfoKind::Veth),
+                nlas::link::Info::SlaveKind(b"bridge\0".to_vec()),
+            ]));
+        }
+        message
+    }
+
+    #[test]
+    fn bridge_family_messages_keep_the_port_ignored() {
+        let mut state = Interfaces::new(Filter {
+            bridge_ports: false,
+            exclude_kinds: ["veth".to_owned()].into_iter().collect(),
+            ..Filter::default()
+        });
+
+        let port = parse_link(&bridge_port_message(constants::AF_UNSPEC)).unwrap();
+        assert_eq!(port.kind.as_deref(), Some("veth"));
+        assert!(port.bridge_port);
+        assert_eq!(state.add_link(port), None);
+        assert!(state.snapshots().is_empty());
+
+        // the kernel sends these for bridge ports on every change of the port, including its removal from the bridge
+        assert!(parse_link(&bridge_port_message(AF_BRIDGE)).is_none());
+    }
+
+    /// Adds a dummy link, or a veth pair when dummy links aren't supported, with an address and a default route
+    async fn configure(handle: &Handle) -> Result<(), rtnetlink::Error> {
+        let name = "dummy0".to_owned();
+        if handle
+            .link()
+            .add()
+            .dummy(name.clone())
+            .execute()
+            .await
+            .is_err()
+        {
+            let peer = "peer0".to_owned();
+            handle
+                .link()
+                .add()
+                .veth(name.clone(), peer.clone())
+                .execute()
+                .await?;
+            let peer_link = handle
+                .link()
+                .get()
+                .match_name(peer)
+                .execute()
+                .try_next()
+                .await?;
+            if let Some(link) = peer_link {
+                handle.link().set(link.header.index).up().execute().await?;
+            }
+        }
+        let link = handle
+            .link()
+            .get()
+            .match_name(name)
+            .execute()
+            .try_next()
+            .await?;
+        let index = link.map(|link| link.header.index).unwrap_or_default();
+        handle.link().set(index).up().execute().await?;
+        handle
+            .address()
+            .add(index, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 24)
+            .execute()
+            .await?;
+        handle
+            .route()
+            .add()
+            .v4()
+            .gateway(Ipv4Addr::new(10, 0, 0, 1))
+            .output_interface(index)
+            .execute()
+            .await?;
+        // the operational state of a veth link follows its peer with a delay
+        for _ in 0..500 {
+            let link = handle
+                .link()
+                .get()
+                .match_index(index)
+                .execute()
+                .try_next()
+                .await?;
+            if link.map_or(false, |link| link_state(&link) == LinkState::Up) {
+                break;
+            }
+            runtime::sleep_until(Instant::now() + Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
+
+    /// Creates a network namespace with the configured dummy link
+    fn network_namespace() -> NetworkNamespace {
+        // the namespace is created on a separate thread so this thread stays in the network namespace of the process
+        let created = thread::spawn(|| {
+            // SAFETY:
+            // unshare only moves the calling thread, which ends after the namespace is configured
+            let unshared = unsafe { libc::unshare(libc::CLONE_NEWNET) };
+            assert_eq!(unshared, 0, "{}", io::Error::last_os_error());
+            runtime::block_on(async {
+                let (connection, handle, _) = new_connection_with_socket::<Socket>().unwrap();
+                select_biased! {
+                    configured = configure(&handle).fuse() => configured.unwrap(),
+                    _ = connection.fuse() => panic!("rtnetlink connection closed"),
+                }
+            })
+            .unwrap();
+            OwnedFd::from(File::open("/proc/thread-self/ns/net").unwrap())
+        })
+        .join()
+        .unwrap();
+        NetworkNamespace::Fd(Arc::new(created))
+    }
+
+    #[test]
+    #[ignore = "creating a network namespace requires CAP_SYS_ADMIN"]
+    fn namespace_is_monitored() {
+        let namespace = network_namespace();
+        let builder = ConnectivityMonitor::builder()
+            .ipv6(false)
+            .namespace(namespace);
+
+        let connectivity = runtime::block_on(builder.clone().current_connectivity())
+            .unwrap()
+            .unwrap();
+        assert_eq!(connectivity.ipv4, ConnectivityState::Internet);
+
+        let snapshots = runtime::block_on(async {
+            let (driver, mut stream) = builder.clone().build_snapshots().unwrap();
+            select_biased! {
+                snapshots = stream.recv().fuse() => snapshots,
+                _ = driver.fuse() => None,
+            }
+        })
+        .unwrap()
+        .unwrap();
+        let dummy = snapshots
+            .iter()
+            .find(|snapshot| snapshot.name.as_deref() == Some("dummy0"))
+            .unwrap();
+        assert_eq!(dummy.ipv4.state, ConnectivityState::Internet);
+        assert_eq!(dummy.ipv4.addresses[0].address, Ipv4Addr::new(10, 0, 0, 2));
+
+        let probed = builder
+            .probe(Probe::Tcp {
+                host: "10.0.0.1".to_owned(),
+                port: 80,
+            })
+            .build();
+        assert!(matches!(probed, Err(Error::Probe(_))));
+    }
+
+    #[test]
+    fn resync_is_limited_without_supervision() {
+        for lost in 0..RECONNECT_ATTEMPTS {
+            assert_eq!(
+                resync_delay(None, lost),
+                Some(RECONNECT_BACKOFF.delay(lost))
+            );
+        }
+        assert_eq!(resync_delay(None, RECONNECT_ATTEMPTS), None);
+
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+        };
+        assert_eq!(resync_delay(Some(backoff), 0), Some(backoff.initial));
+        assert_eq!(
+            resync_delay(Some(backoff), RECONNECT_ATTEMPTS),
+            Some(backoff.max)
+        );
+        assert_eq!(resync_delay(Some(backoff), u32::MAX), Some(backoff.max));
+    }
+
+    /// Adds count addresses to the dummy link in namespace from another thread
+    fn add_addresses(namespace: &NetworkNamespace, count: u16) {
+        let fd = match *namespace {
+            NetworkNamespace::Fd(ref fd) => fd.try_clone().unwrap(),
+            NetworkNamespace::Path(ref path) => File::open(path).unwrap().into(),
+        };
+        thread::spawn(move || {
+            set_namespace(fd.as_raw_fd()).unwrap();
+            runtime::block_on(async {
+                let (connection, handle, _) = new_connection_with_socket::<Socket>().unwrap();
+                let added = async {
+                    let link = handle
+                        .link()
+                        .get()
+                        .match_name("dummy0".to_owned())
+                        .execute()
+                        .try_next()
+                        .await?;
+                    let index = link.map(|link| link.header.index).unwrap_or_default();
+                    for address in 0..count {
+                        let [high, low] = address.to_be_bytes();
+                        handle
+                            .address()
+                            .add(index, IpAddr::V4(Ipv4Addr::new(10, 1, high, low)), 32)
+                            .execute()
+                            .await?;
+                    }
+                    Ok::<_, rtnetlink::Error>(())
+                };
+                select_biased! {
+                    result = added.fuse() => result.unwrap(),
+                    _ = connection.fuse() => panic!("rtnetlink connection closed"),
+                }
+            })
+            .unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    #[ignore = "creating a network namespace requires CAP_SYS_ADMIN"]
+    fn overrun_is_resynchronised() {
+        let namespace = network_namespace();
+        let builder = ConnectivityMonitor::builder()
+            .ipv6(false)
+            .namespace(namespace.clone());
+        let resynced = runtime::block_on(async {
+            let (driver, mut stream) = builder.build().unwrap();
+            let checked = async {
+                let initial = stream.recv().await;
+                // the driver runs on this thread, so its socket buffer overruns while the addresses are added
+                add_addresses(&namespace, 2000);
+                for _ in 0..50 {
+                    if stream.resyncs() > 0 {
+                        break;
+                    }
+                    runtime::timeout(Duration::from_millis(100), stream.recv()).await;
+                }
+                (initial, stream.resyncs(), stream.health())
+            };
+            select_biased! {
+                checked = checked.fuse() => checked,
+                result = driver.fuse() => panic!("driver stopped {result:?}"),
+            }
+        })
+        .unwrap();
+        let (initial, resyncs, health) = resynced;
+        assert_eq!(initial.unwrap().ipv4, ConnectivityState::Internet);
+        assert!(resyncs > 0);
+        assert_eq!(health, crate::MonitorHealth::Running);
+    }
+}
@@ -0,0 +1,637 @@
+// SPDX-License-Identifier: MIT
+
+//! Active verification of the internet connectivity detected from the routes
+
+use crate::{
+    runtime::{self, TcpStream},
+    state::Path,
+    Connectivity, ConnectivityState, Error, InterfaceSnapshot, Probe,
+};
+use core::{future::Future, pin::Pin, time::Duration};
+use futures::{future, select_biased, FutureExt};
+use log::debug;
+use std::{net::SocketAddr, time::Instant};
+
+/// The maximum number of bytes read from an HTTP response
+const MAX_RESPONSE_LENGTH: u64 = 64 * 1024;
+
+/// A probe that is in progress, resolving to the [`Reachability`] of the internet
+type PendingProbe = Pin<Box<dyn Future<Output = Reachability> + Send>>;
+
+/// Configures if and how the connectivity is verified.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// The probe to verify the connectivity with, nothing is verified when it isn't set
+    pub probe: Option<Probe>,
+    /// The period after which a verified connectivity is probed again
+    pub interval: Duration,
+    /// The period after which a probe is considered failed
+    pub timeout: Duration,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            probe: None,
+            interval: Duration::from_secs(300),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The ip type a probe is restricted to.
+#[derive(Clone, Copy, Debug)]
+enum IpType {
+    /// Only ipv4 addresses are used
+    V4,
+    /// Only ipv6 addresses are used
+    V6,
+}
+impl IpType {
+    /// Whether address is of this ip type
+    fn matches(self, address: &SocketAddr) -> bool {
+        match self {
+            Self::V4 => address.is_ipv4(),
+            Self::V6 => address.is_ipv6(),
+        }
+    }
+}
+
+/// The result of a probe.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Reachability {
+    /// The internet is not reachable
+    Unreachable,
+    /// The internet is reachable
+    Reachable,
+    /// The probe was answered by the captive portal with the contained url
+    Portal(String),
+}
+
+/// The progress of a probe for a single ip type.
+enum Progress {
+    /// A probe completed with the contained [`Reachability`]
+    Probed(Reachability),
+    /// The interval passed and a new probe should start
+    Due,
+}
+
+/// Records the probing state for a single ip type.
+struct Verification {
+    /// The ip type that is probed
+    ip_type: IpType,
+    /// The [`Reachability`] found by the last completed probe
+    reachability: Reachability,
+    /// The probe that is in progress
+    pending: Option<PendingProbe>,
+    /// The moment the next periodic probe starts
+    next: Option<Instant>,
+    /// The paths to the internet the last probe was requested for
+    paths: Vec<Path>,
+    /// Whether the paths changed while a probe was in progress, so its result is discarded and it is repeated when it completes
+    stale: bool,
+}
+impl Verification {
+    /// Create a new [`Verification`] instance that has not verified anything yet
+    const fn new(ip_type: IpType) -> Self {
+        Self {
+            ip_type,
+            reachability: Reachability::Unreachable,
+            pending: None,
+            next: None,
+            paths: Vec::new(),
+            stale: false,
+        }
+    }
+
+    /// Forget everything that was verified and stop probing
+    fn reset(&mut self) {
+        self.reachability = Reachability::Unreachable;
+        self.pending = None;
+        self.next = None;
+        self.paths.clear();
+        self.stale = false;
+    }
+
+    /// Starts probing when the paths to the internet changed
+    ///
+    /// What was verified for the previous paths doesn't apply to the new paths, so the verification is lost until the new paths are probed.
+    /// A probe that is in progress is not interrupted, its result is discarded and it is repeated once it completes instead.
+    /// Without paths to the internet nothing is probed.
+    fn request(&mut self, paths: Vec<Path>, probe: &Probe, timeout: Duration) {
+        if paths.is_empty() {
+            self.reset();
+            return;
+        }
+        if paths == self.paths {
+            return;
+        }
+        self.paths = paths;
+        self.reachability = Reachability::Unreachable;
+        if self.pending.is_some() {
+            debug!("repeating probe {:?} when it completes", self.ip_type);
+            self.stale = true;
+        } else {
+            debug!("probing {:?}", self.ip_type);
+            self.next = None;
+            self.pending = Some(Box::pin(reachable(probe.clone(), self.ip_type, timeout)));
+        }
+    }
+
+    /// Limits the routed state to what was verified
+    ///
+    /// # Returns
+    ///
+    /// The verified state and the url of the captive portal if there is one
+    fn verify(&self, routed: ConnectivityState) -> (ConnectivityState, Option<&str>) {
+        if routed != ConnectivityState::Internet {
+            return (routed, None);
+        }
+        match self.reachability {
+            Reachability::Unreachable => (ConnectivityState::Network, None),
+            Reachability::Reachable => (ConnectivityState::Internet, None),
+            Reachability::Portal(ref url) => (ConnectivityState::CaptivePortal, Some(url)),
+        }
+    }
+
+    /// Get the index of the interface with the default route of the lowest priority, through which the probe reaches the internet
+    fn preferred(&self) -> Option<u32> {
+        self.paths
+            .iter()
+            .filter_map(|path| {
+                let priority = path.gateways.iter().map(|&(_, priority)| priority).min()?;
+                Some((priority, path.index))
+            })
+            .min()
+            .map(|(_, index)| index)
+    }
+
+    /// Limits the routed state of the interface with index to what was verified
+    ///
+    /// Only the interface the probe reaches the internet through is verified, the internet connectivity of other interfaces is limited to [`ConnectivityState::Network`].
+    ///
+    /// # Returns
+    ///
+    /// The verified state and the url of the captive portal if there is one
+    fn verify_interface(
+        &self,
+        index: u32,
+        routed: ConnectivityState,
+    ) -> (ConnectivityState, Option<&str>) {
+        if routed == ConnectivityState::Internet && self.preferred() != Some(index) {
+            return (ConnectivityState::Network, None);
+        }
+        self.verify(routed)
+    }
+
+    /// Completes when the pending probe completes or the next periodic probe is due
+    async fn progress(&mut self) -> Progress {
+        match (self.pending.as_mut(), self.next) {
+            (Some(pending), _) => Progress::Probed(pending.await),
+            (None, Some(next)) => {
+                runtime::sleep_until(next).await;
+                Progress::Due
+            }
+            (None, None) => future::pending().await,
+        }
+    }
+}
+
+/// Verifies the internet connectivity of each ip type with a [Probe].
+pub struct Prober {
+    /// The probe to verify the connectivity with
+    probe: Probe,
+    /// The period after which a verified connectivity is probed again
+    interval: Duration,
+    /// The period after which a probe is considered failed
+    timeout: Duration,
+    /// The ipv4 [`Verification`]
+    ipv4: Verification,
+    /// The ipv6 [`Verification`]
+    ipv6: Verification,
+}
+impl Prober {
+    /// Create a new [`Prober`] instance when settings contains a probe
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the probe is invalid.
+    pub(crate) fn new(settings: Settings) -> Result<Option<Self>, Error> {
+        let probe = match settings.probe {
+            Some(probe) => probe,
+            None => return Ok(None),
+        };
+        if let Probe::Http { ref url, .. } = probe {
+            parse_url(url)
+                .ok_or_else(|| Error::Probe(format!("unsupported probe url {url}").into()))?;
+        }
+        Ok(Some(Self {
+            probe,
+            interval: settings.interval,
+            timeout: settings.timeout,
+            ipv4: Verification::new(IpType::V4),
+            ipv6: Verification::new(IpType::V6),
+        }))
+    }
+
+    /// Starts a probe for every ip type of which the paths to the internet changed.
+    ///
+    /// Ip types without paths to the internet are no longer probed and lose their verification.
+    pub(crate) fn request(&mut self, paths: (Vec<Path>, Vec<Path>)) {
+        let (ipv4_paths, ipv6_paths) = paths;
+        self.ipv4.request(ipv4_paths, &self.probe, self.timeout);
+        self.ipv6.request(ipv6_paths, &self.probe, self.timeout);
+    }
+
+    /// Limits the routed connectivity to what was verified for each ip type
+    pub(crate) fn verify(&self, routed: Connectivity) -> Connectivity {
+        Connectivity {
+            ipv4: self.ipv4.verify(routed.ipv4).0,
+            ipv6: self.ipv6.verify(routed.ipv6).0,
+        }
+    }
+
+    /// Limits the routed state of snapshot to what was verified for each ip type and records the captive portal
+    ///
+    /// The probe of an ip type is only attributed to the interface with the default route of the lowest priority of that ip type.
+    pub(crate) fn verify_snapshot(&self, snapshot: &mut InterfaceSnapshot) {
+        let (ipv4_state, ipv4_portal) = self
+            .ipv4
+            .verify_interface(snapshot.index, snapshot.ipv4.state);
+        snapshot.ipv4.state = ipv4_state;
+        snapshot.ipv4.portal = ipv4_portal.map(str::to_owned);
+        let (ipv6_state, ipv6_portal) = self
+            .ipv6
+            .verify_interface(snapshot.index, snapshot.ipv6.state);
+        snapshot.ipv6.state = ipv6_state;
+        snapshot.ipv6.portal = ipv6_portal.map(str::to_owned);
+    }
+
+    /// Completes when a probe completed, starting the periodic probes that are due in the meantime
+    pub(crate) async fn probed(&mut self) {
+        loop {
+            let (ip_type, progress) = select_biased! {
+                progress = self.ipv4.progress().fuse() => (IpType::V4, progress),
+                progress = self.ipv6.progress().fuse() => (IpType::V6, progress),
+            };
+            let verification = match ip_type {
+                IpType::V4 => &mut self.ipv4,
+                IpType::V6 => &mut self.ipv6,
+            };
+            match progress {
+                Progress::Probed(reachability) => {
+                    verification.pending = None;
+                    verification.next = if verification.stale {
+                        debug!(
+                            "discarding probe {:?} {:?} of changed paths",
+                            verification.ip_type, reachability
+                        );
+                        verification.stale = false;
+                        Some(Instant::now())
+                    } else {
+                        debug!("probed {:?} {:?}", verification.ip_type, reachability);
+                        verification.reachability = reachability;
+                        Instant::now().checked_add(self.interval)
+                    };
+                    return;
+                }
+                Progress::Due => {
+                    debug!("probing {:?} periodically", verification.ip_type);
+                    verification.next = None;
+                    verification.pending = Some(Box::pin(reachable(
+                        self.probe.clone(),
+                        verification.ip_type,
+                        self.timeout,
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// Splits an `http://` url into its authority, host, port and path
+fn parse_url(url: &str) -> Option<(&str, &str, u16, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = rest
+        .find('/')
+        .map_or((rest, "/"), |position| rest.split_at(position));
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, port) = bracketed.split_once(']')?;
+        if port.is_empty() {
+            (host, 80)
+        } else {
+            (host, port.strip_prefix(':')?.parse().ok()?)
+        }
+    } else if let Some((host, port)) = authority.split_once(':') {
+        (host, port.parse().ok()?)
+    } else {
+        (authority, 80)
+    };
+    (!host.is_empty()).then_some((authority, host, port, path))
+}
+
+/// Opens a TCP connection to the first address of ip type host resolves to that accepts it
+///
+/// # Errors
+///
+/// This function will return an error if host couldn't be resolved or no connection could be made.
+async fn connect(host: &str, port: u16, ip_type: IpType) -> Result<TcpStream, Error> {
+    let mut last_error = Error::Probe(format!("{host} has no {ip_type:?} address").into());
+    for address in runtime::lookup_host(host, port)
+        .await
+        .map_err(|error| Error::Probe(Box::new(error)))?
+        .into_iter()
+        .filter(|address| ip_type.matches(address))
+    {
+        match runtime::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Error::Probe(Box::new(error)),
+        }
+    }
+    Err(last_error)
+}
+
+/// Performs probe using addresses of ip type
+///
+/// # Errors
+///
+/// This function will return an error if the probe couldn't be performed.
+async fn check(probe: Probe, ip_type: IpType) -> Result<Reachability, Error> {
+    match probe {
+        Probe::Http { url, status, body } => {
+            let (authority, host, port, path) = parse_url(&url)
+                .ok_or_else(|| Error::Probe(format!("unsupported probe url {url}").into()))?;
+            let stream = connect(host, port, ip_type).await?;
+            let request = format!(
+                "GET {path} HTTP/1.0\r\nHost: {authority}\r\nUser-Agent: network_connectivity\r\nConnection: close\r\n\r\n"
+            );
+            let response = runtime::exchange(stream, request.as_bytes(), MAX_RESPONSE_LENGTH)
+                .await
+                .map_err(|error| Error::Probe(Box::new(error)))?;
+            let text = String::from_utf8_lossy(&response);
+            let (head, content) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+            let received_status = head
+                .split(' ')
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| Error::Probe("invalid HTTP response".into()))?;
+            if received_status == status
+                && body.map_or(true, |expected| content.contains(&expected))
+            {
+                return Ok(Reachability::Reachable);
+            }
+            // a captive portal redirects or answers with its own page, any other response is an error of the probed server
+            if !(received_status == 200 || (300..400).contains(&received_status)) {
+                debug!("probe answered with status {}", received_status);
+                return Ok(Reachability::Unreachable);
+            }
+            let location = head.lines().skip(1).find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("location")
+                    .then_some(value.trim())
+            });
+            let portal = match location {
+                Some(absolute_path) if absolute_path.starts_with('/') => {
+                    format!("http://{authority}{absolute_path}")
+                }
+                Some(redirect) => redirect.to_owned(),
+                None => url.clone(),
+            };
+            Ok(Reachability::Portal(portal))
+        }
+        Probe::Tcp { host, port } => {
+            connect(&host, port, ip_type).await?;
+            Ok(Reachability::Reachable)
+        }
+        Probe::Dns { host } => {
+            let addresses = runtime::lookup_host(&host, 0)
+                .await
+                .map_err(|error| Error::Probe(Box::new(error)))?;
+            Ok(
+                if addresses.iter().any(|address| ip_type.matches(address)) {
+                    Reachability::Reachable
+                } else {
+                    Reachability::Unreachable
+                },
+            )
+        }
+    }
+}
+
+/// Performs probe using addresses of ip type within timeout
+async fn reachable(probe: Probe, ip_type: IpType, period: Duration) -> Reachability {
+    match runtime::timeout(period, check(probe, ip_type)).await {
+        Some(Ok(reachability)) => reachability,
+        Some(Err(error)) => {
+            debug!("probe {:?} failed {}", ip_type, error);
+            Reachability::Unreachable
+        }
+        None => {
+            debug!("probe {:?} timed out", ip_type);
+            Reachability::Unreachable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    /// Answers a single request on a local port with response
+    ///
+    /// # Returns
+    ///
+    /// The authority of the server and a handle that resolves to the request it received
+    fn serve(response: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let authority = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let length = stream.read(&mut buffer).unwrap();
+                assert_ne!(
+                    length, 0,
+                    "connection closed before the request was complete"
+                );
+                request.extend_from_slice(&buffer[..length]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (authority, server)
+    }
+
+    /// Create an HTTP probe for url
+    fn http(url: String, status: u16, body: Option<&str>) -> Probe {
+        Probe::Http {
+            url,
+            status,
+            body: body.map(str::to_owned),
+        }
+    }
+
+    /// Create a path through the interface with index with a default route
+    fn path(index: u32) -> Path {
+        Path {
+            index,
+            addresses: vec![[192, 168, 1, 2].into()],
+            gateways: vec![(Some([192, 168, 1, 1].into()), 0)],
+        }
+    }
+
+    /// Get the verified connectivity when ipv4 is routed to the internet
+    fn verified(prober: &Prober) -> ConnectivityState {
+        prober
+            .verify(Connectivity {
+                ipv4: ConnectivityState::Internet,
+                ipv6: ConnectivityState::None,
+            })
+            .ipv4
+    }
+
+    #[test]
+    fn changed_paths_discard_the_pending_probe() {
+        let mut prober = Prober::new(Settings {
+            probe: Some(Probe::Tcp {
+                host: "127.0.0.1".to_owned(),
+                port: 9,
+            }),
+            ..Settings::default()
+        })
+        .unwrap()
+        .unwrap();
+
+        prober.request((vec![path(2)], Vec::new()));
+        assert_eq!(verified(&prober), ConnectivityState::Network);
+        prober.ipv4.pending = Some(Box::pin(future::ready(Reachability::Reachable)));
+        runtime::block_on(prober.probed()).unwrap();
+        assert_eq!(verified(&prober), ConnectivityState::Internet);
+
+        // a periodic probe through the first interface is still in progress when the paths change
+        let (reachable_tx, reachable_rx) = oneshot::channel();
+        prober.ipv4.pending =
+            Some(Box::pin(reachable_rx.map(|received| {
+                received.unwrap_or(Reachability::Unreachable)
+            })));
+        prober.request((vec![path(3)], Vec::new()));
+        assert_eq!(verified(&prober), ConnectivityState::Network);
+
+        reachable_tx.send(Reachability::Reachable).unwrap();
+        let completed = Instant::now();
+        runtime::block_on(prober.probed()).unwrap();
+        assert_eq!(verified(&prober), ConnectivityState::Network);
+        assert!(prober.ipv4.pending.is_none());
+        assert!(!prober.ipv4.stale);
+        // the probe is repeated for the new paths right away
+        assert!(prober
+            .ipv4
+            .next
+            .map_or(false, |next| next <= Instant::now() && next >= completed));
+    }
+
+    #[test]
+    fn parse_url_splits_authority_host_port_and_path() {
+        assert_eq!(
+            parse_url("http://example.com"),
+            Some(("example.com", "example.com", 80, "/"))
+        );
+        assert_eq!(
+            parse_url("http://example.com:8080/generate_204"),
+            Some(("example.com:8080", "example.com", 8080, "/generate_204"))
+        );
+        assert_eq!(
+            parse_url("http://[::1]/check?x=1"),
+            Some(("[::1]", "::1", 80, "/check?x=1"))
+        );
+        assert_eq!(
+            parse_url("http://[::1]:81/"),
+            Some(("[::1]:81", "::1", 81, "/"))
+        );
+        assert_eq!(parse_url("https://example.com/"), None);
+        assert_eq!(parse_url("http:///path"), None);
+        assert_eq!(parse_url("http://example.com:port/"), None);
+        assert_eq!(parse_url("http://[::1]x/"), None);
+    }
+
+    #[test]
+    fn expected_status_is_reachable() {
+        let (authority, server) = serve("HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+        let probe = http(format!("http://{authority}/generate_204"), 204, None);
+        let reachability = runtime::block_on(check(probe, IpType::V4)).unwrap();
+        assert_eq!(reachability.unwrap(), Reachability::Reachable);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /generate_204 HTTP/1.0\r\n"));
+        assert!(request.contains(&format!("\r\nHost: {authority}\r\n")));
+    }
+
+    #[test]
+    fn relative_redirect_is_portal_on_same_authority() {
+        let (authority, server) = serve("HTTP/1.1 302 Found\r\nlocation: /login?next=%2F\r\n\r\n");
+        let probe = http(format!("http://{authority}/generate_204"), 204, None);
+        let reachability = runtime::block_on(check(probe, IpType::V4)).unwrap();
+        assert_eq!(
+            reachability.unwrap(),
+            Reachability::Portal(format!("http://{authority}/login?next=%2F"))
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn wrong_body_is_portal_on_probe_url() {
+        let (authority, server) =
+            serve("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html>Sign in</html>");
+        let url = format!("http://{authority}/success.txt");
+        let probe = http(url.clone(), 200, Some("success"));
+        let reachability = runtime::block_on(check(probe, IpType::V4)).unwrap();
+        assert_eq!(reachability.unwrap(), Reachability::Portal(url));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_error_is_unreachable() {
+        let (authority, server) =
+            serve("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+        let probe = http(format!("http://{authority}/generate_204"), 204, None);
+        let reachability = runtime::block_on(check(probe, IpType::V4)).unwrap();
+        assert_eq!(reachability.unwrap(), Reachability::Unreachable);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn silent_server_times_out() {
+        // the connection is accepted by the system but nothing is ever answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/generate_204", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let reachability = runtime::block_on(reachable(
+            http(url, 204, None),
+            IpType::V4,
+            Duration::from_millis(200),
+        ))
+        .unwrap();
+        assert_eq!(reachability, Reachability::Unreachable);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn other_ip_type_is_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let reachability = runtime::block_on(reachable(
+            http(url, 204, None),
+            IpType::V6,
+            Duration::from_secs(1),
+        ))
+        .unwrap();
+        assert_eq!(reachability, Reachability::Unreachable);
+    }
+}
@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: MIT
+
+//! The parts of the async runtime selected with a cargo feature that this crate depends on
+
+use core::time::Duration;
+use futures::{
+    future::{self, Either},
+    pin_mut, Future,
+};
+use std::{io, net::SocketAddr, time::Instant};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "runtime-tokio")] {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        /// The TCP stream of the runtime
+        pub type TcpStream = tokio::net::TcpStream;
+        /// The rtnetlink socket of the runtime
+        #[cfg(target_os = "linux")]
+        pub type Socket = rtnetlink::sys::TokioSocket;
+
+        /// Completes when deadline is reached
+        pub async fn sleep_until(deadline: Instant) {
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+
+        /// Resolves host to the socket addresses for port
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if host couldn't be resolved.
+        pub async fn lookup_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(tokio::net::lookup_host((host, port)).await?.collect())
+        }
+
+        /// Writes request to stream and reads the response until stream is closed or limit bytes are read
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if writing or reading failed.
+        pub async fn exchange(mut stream: TcpStream, request: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+            stream.write_all(request).await?;
+            let mut response = Vec::new();
+            stream.take(limit).read_to_end(&mut response).await?;
+            Ok(response)
+        }
+
+        /// Runs future to completion on the current thread
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if the runtime couldn't be created.
+        pub fn block_on<F>(future: F) -> io::Result<F::Output>
+        where
+            F: Future,
+        {
+            Ok(tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(future))
+        }
+    } else if #[cfg(feature = "runtime-async-std")] {
+        use async_std::net::ToSocketAddrs;
+        use futures::{AsyncReadExt, AsyncWriteExt};
+
+        /// The TCP stream of the runtime
+        pub type TcpStream = async_std::net::TcpStream;
+        /// The rtnetlink socket of the runtime
+        #[cfg(target_os = "linux")]
+        pub type Socket = rtnetlink::sys::SmolSocket;
+
+        /// Completes when deadline is reached
+        pub async fn sleep_until(deadline: Instant) {
+            async_std::task::sleep(deadline.saturating_duration_since(Instant::now())).await;
+        }
+
+        /// Resolves host to the socket addresses for port
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if host couldn't be resolved.
+        pub async fn lookup_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok((host, port).to_socket_addrs().await?.collect())
+        }
+
+        /// Writes request to stream and reads the response until stream is closed or limit bytes are read
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if writing or reading failed.
+        pub async fn exchange(mut stream: TcpStream, request: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+            stream.write_all(request).await?;
+            let mut response = Vec::new();
+            stream.take(limit).read_to_end(&mut response).await?;
+            Ok(response)
+        }
+
+        /// Runs future to completion on the current thread
+        ///
+        /// # Errors
+        ///
+        /// This function never returns an error, the result is for compatibility with the other runtimes.
+        #[allow(clippy::unnecessary_wraps)]
+        pub fn block_on<F>(future: F) -> io::Result<F::Output>
+        where
+            F: Future,
+        {
+            Ok(async_std::task::block_on(future))
+        }
+    } else if #[cfg(feature = "runtime-smol")] {
+        use futures::{AsyncReadExt, AsyncWriteExt};
+
+        /// The TCP stream of the runtime
+        pub type TcpStream = smol::net::TcpStream;
+        /// The rtnetlink socket of the runtime
+        #[cfg(target_os = "linux")]
+        pub type Socket = rtnetlink::sys::SmolSocket;
+
+        /// Completes when deadline is reached
+        pub async fn sleep_until(deadline: Instant) {
+            smol::Timer::at(deadline).await;
+        }
+
+        /// Resolves host to the socket addresses for port
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if host couldn't be resolved.
+        pub async fn lookup_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            smol::net::resolve((host, port)).await
+        }
+
+        /// Writes request to stream and reads the response until stream is closed or limit bytes are read
+        ///
+        /// # Errors
+        ///
+        /// This function will return an error if writing or reading failed.
+        pub async fn exchange(mut stream: TcpStream, request: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+            stream.write_all(request).await?;
+            let mut response = Vec::new();
+            stream.take(limit).read_to_end(&mut response).await?;
+            Ok(response)
+        }
+
+        /// Runs future to completion on the current thread
+        ///
+        /// # Errors
+        ///
+        /// This function never returns an error, the result is for compatibility with the other runtimes.
+        #[allow(clippy::unnecessary_wraps)]
+        pub fn block_on<F>(future: F) -> io::Result<F::Output>
+        where
+            F: Future,
+        {
+            Ok(smol::block_on(future))
+        }
+    } else {
+        compile_error!("This crate requires one of the features runtime-tokio, runtime-async-std or runtime-smol.");
+    }
+}
+
+/// Opens a TCP connection to address
+///
+/// # Errors
+///
+/// This function will return an error if the connection couldn't be made.
+pub async fn connect(address: SocketAddr) -> io::Result<TcpStream> {
+    TcpStream::connect(address).await
+}
+
+/// Runs future until it completes or period passed
+///
+/// # Returns
+///
+/// The output of future or [None] when period passed first.
+pub async fn timeout<F>(period: Duration, future: F) -> Option<F::Output>
+where
+    F: Future,
+{
+    let deadline = match Instant::now().checked_add(period) {
+        Some(deadline) => deadline,
+        None => return Some(future.await),
+    };
+    let sleep = sleep_until(deadline);
+    pin_mut!(future, sleep);
+    match future::select(future, sleep).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,898 @@
+// SPDX-License-Identifier: MIT
+
+//! The platform independent channels through which drivers emit updates
+
+use crate::runtime::sleep_until;
+use crate::{
+    probe::Prober, state::Interfaces, Connectivity, ConnectivityState, Error, Event,
+    InterfaceSnapshot, MonitorHealth,
+};
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{
+    fmt,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use futures::{
+    channel::mpsc::UnboundedSender,
+    future::{self, Shared},
+    select_biased, Future, FutureExt,
+};
+use log::debug;
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+use tokio::sync::watch;
+
+/// A future that completes when the receive end of a channel is dropped
+pub type Closed = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// assigns to assign from assignee only when they are different,
+///
+/// # Returns
+///
+/// true if the arguments were different and false otherwise
+fn diff_assign<T>(assign: &mut T, assignee: T) -> bool
+where
+    T: Eq,
+{
+    if *assign == assignee {
+        false
+    } else {
+        *assign = assignee;
+        true
+    }
+}
+
+/// A callback which is called with the old and the new [Connectivity] when the connectivity changed
+pub type Callback = dyn Fn(Connectivity, Connectivity) + Send + Sync;
+
+/// The registered callbacks and the identifier for the next registration.
+struct Registry {
+    /// The identifier the next registered callback gets
+    next: u64,
+    /// The registered callbacks by their identifier, so they are called in the order they were registered
+    callbacks: BTreeMap<u64, Arc<Callback>>,
+}
+
+/// A shared list of callbacks which are called by a driver when the connectivity changed.
+#[derive(Clone)]
+pub struct Callbacks {
+    /// The shared registry
+    registry: Arc<Mutex<Registry>>,
+}
+impl Default for Callbacks {
+    fn default() -> Self {
+        Self {
+            registry: Arc::new(Mutex::new(Registry {
+                next: 0,
+                callbacks: BTreeMap::new(),
+            })),
+        }
+    }
+}
+impl fmt::Debug for Callbacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("registered", &self.lock().callbacks.len())
+            .finish()
+    }
+}
+impl Callbacks {
+    /// Locks the registry, a callback that panicked can't leave it inconsistent
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers callback
+    ///
+    /// # Returns
+    ///
+    /// The identifier with which callback is unregistered
+    pub(crate) fn register(&self, callback: Arc<Callback>) -> u64 {
+        let mut registry = self.lock();
+        let id = registry.next;
+        registry.next = registry.next.wrapping_add(1);
+        registry.callbacks.insert(id, callback);
+        id
+    }
+
+    /// Unregisters the callback with identifier id
+    pub(crate) fn unregister(&self, id: u64) {
+        self.lock().callbacks.remove(&id);
+    }
+
+    /// Calls all registered callbacks with old and new.
+    ///
+    /// The registry is not locked while the callbacks are called, so they may register and unregister callbacks.
+    fn call(&self, old: Connectivity, new: Connectivity) {
+        let callbacks: Vec<Arc<Callback>> = self.lock().callbacks.values().cloned().collect();
+        for callback in callbacks {
+            callback(old, new);
+        }
+    }
+}
+
+/// Configures the periods an update must be stable before it is emitted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Debounce {
+    /// The period for updates that don't lower the connectivity of any ip type
+    pub upgrade: Duration,
+    /// The period for updates that lower the connectivity of any ip type
+    pub downgrade: Duration,
+}
+impl Debounce {
+    /// Get the period the update from the emitted to the observed connectivity must be stable
+    fn period(self, emitted: Option<Connectivity>, observed: Connectivity) -> Duration {
+        match emitted {
+            Some(old) if observed.ipv4 < old.ipv4 || observed.ipv6 < old.ipv6 => self.downgrade,
+            Some(_) | None => self.upgrade,
+        }
+    }
+}
+
+/// Configures the exponential backoff with which a failed driver is restarted.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct Backoff {
+    /// The delay before the first restart
+    pub initial: Duration,
+    /// The maximum delay between restarts
+    pub max: Duration,
+}
+impl Backoff {
+    /// Get the delay before restart attempt, which doubles for every attempt from the initial delay up to the maximum delay
+    #[cfg(target_os = "linux")]
+    pub(crate) fn delay(self, attempt: u32) -> Duration {
+        u32::checked_pow(2, attempt)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+/// The health and counters a driver records for monitoring.
+#[derive(Debug, Default)]
+pub struct Statistics {
+    /// The number of times the state was resynchronised with the system because notifications were lost
+    resyncs: AtomicU64,
+    /// The number of times the driver restarted after it failed
+    restarts: AtomicU64,
+    /// The health of the driver
+    health: Mutex<MonitorHealth>,
+}
+impl Statistics {
+    /// Get the number of times the state was resynchronised with the system
+    pub(crate) fn resyncs(&self) -> u64 {
+        self.resyncs.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of times the driver restarted after it failed
+    pub(crate) fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Get the health of the driver
+    pub(crate) fn health(&self) -> MonitorHealth {
+        *self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records the health of the driver
+    pub(crate) fn set_health(&self, health: MonitorHealth) {
+        *self.health.lock().unwrap_or_else(PoisonError::into_inner) = health;
+    }
+}
+
+/// The transmit end of a channel to which a driver emits updates.
+pub enum Sender {
+    /// Emits [Connectivity] updates
+    Connectivity(UnboundedSender<Connectivity>),
+    /// Emits [`InterfaceSnapshot`] updates for all interfaces
+    Snapshots(UnboundedSender<Vec<InterfaceSnapshot>>),
+    /// Emits [Event] updates
+    Events(UnboundedSender<Event>),
+    /// Replaces the current [Connectivity] and calls the [Callbacks] when it changed
+    Watch(Arc<watch::Sender<Connectivity>>, Callbacks),
+}
+
+/// Whether the snapshots are the same apart from the lifetimes of their addresses.
+///
+/// The lifetimes move with every refresh by a router advertisement and every resynchronisation, which doesn't change the interfaces.
+fn same_except_lifetimes(left: &[InterfaceSnapshot], right: &[InterfaceSnapshot]) -> bool {
+    let without_lifetimes = |snapshots: &[InterfaceSnapshot]| {
+        let mut stripped = snapshots.to_vec();
+        for snapshot in &mut stripped {
+            for address in &mut snapshot.ipv4.addresses {
+                address.preferred_until = None;
+                address.valid_until = None;
+            }
+            for address in &mut snapshot.ipv6.addresses {
+                address.preferred_until = None;
+                address.valid_until = None;
+            }
+        }
+        stripped
+    };
+    without_lifetimes(left) == without_lifetimes(right)
+}
+
+/// Tracks the last observed and the last emitted value of a derived update.
+struct Tracker<T> {
+    /// The last observed value
+    observed: Option<T>,
+    /// The last emitted value
+    emitted: Option<T>,
+    /// Whether two values are the same update
+    same: fn(&T, &T) -> bool,
+}
+impl<T> Tracker<T>
+where
+    T: Clone,
+{
+    /// Create a new [`Tracker`] instance which has not observed anything yet and considers values the same update when same returns true
+    const fn new(same: fn(&T, &T) -> bool) -> Self {
+        Self {
+            observed: None,
+            emitted: None,
+            same,
+        }
+    }
+
+    /// Records value as observed.
+    ///
+    /// # Returns
+    ///
+    /// true if value is another update than the previously observed value
+    fn observe(&mut self, value: T) -> bool {
+        let same = self.same;
+        let changed = self
+            .observed
+            .as_ref()
+            .map_or(true, |observed| !same(observed, &value));
+        self.observed = Some(value);
+        changed
+    }
+
+    /// Records the observed value as emitted.
+    ///
+    /// # Returns
+    ///
+    /// The previously emitted and the observed value when they are another update
+    fn emit(&mut self) -> Option<(Option<T>, T)> {
+        let observed = self.observed.clone()?;
+        let same = self.same;
+        if self
+            .emitted
+            .as_ref()
+            .map_or(false, |emitted| same(emitted, &observed))
+        {
+            return None;
+        }
+        let emitted = self.emitted.replace(observed.clone());
+        Some((emitted, observed))
+    }
+}
+
+/// Emits updates derived from [Interfaces] to a [Sender] only when they changed.
+pub struct Emitter {
+    /// The channel to emit updates to
+    sender: Sender,
+    /// Completes when the receive end of the channel is dropped
+    closed: Shared<Closed>,
+    /// The periods the derived update must be stable before it is emitted
+    debounce: Debounce,
+    /// Verifies the internet connectivity derived from the routes when it is set
+    prober: Option<Prober>,
+    /// The [Connectivity] derived from the routes of the last update
+    routed: Connectivity,
+    /// The [`InterfaceSnapshot`] list derived from the routes of the last update, only recorded for [`Sender::Snapshots`]
+    routed_snapshots: Vec<InterfaceSnapshot>,
+    /// The moment the observed update is stable long enough to be emitted
+    deadline: Option<Instant>,
+    /// The last [Event] which changed the state since the last emit
+    cause: Option<Event>,
+    /// Tracks the [Connectivity]
+    connectivity: Tracker<Connectivity>,
+    /// Tracks the [`InterfaceSnapshot`] list
+    snapshots: Tracker<Vec<InterfaceSnapshot>>,
+    /// The health and counters shared with the handles to the driver
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    statistics: Arc<Statistics>,
+}
+impl Emitter {
+    /// Create a new [`Emitter`] instance which has not emitted anything yet
+    pub(crate) fn new(
+        sender: Sender,
+        closed: Closed,
+        debounce: Debounce,
+        prober: Option<Prober>,
+        statistics: Arc<Statistics>,
+    ) -> Self {
+        Self {
+            sender,
+            closed: closed.shared(),
+            debounce,
+            prober,
+            routed: Connectivity {
+                ipv4: ConnectivityState::None,
+                ipv6: ConnectivityState::None,
+            },
+            routed_snapshots: Vec::new(),
+            deadline: None,
+            cause: None,
+            connectivity: Tracker::new(Connectivity::eq),
+            snapshots: Tracker::new(|left, right| same_except_lifetimes(left, right)),
+            statistics,
+        }
+    }
+
+    /// Get a future that completes when the receive end of the channel of this [`Emitter`] is dropped
+    pub(crate) fn closed(&self) -> Shared<Closed> {
+        self.closed.clone()
+    }
+
+    /// Records that the state was resynchronised with the system
+    #[cfg(target_os = "linux")]
+    pub(crate) fn resynced(&self) {
+        self.statistics.resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the driver failed and restarts
+    ///
+    /// # Returns
+    ///
+    /// Whether the driver was running before it failed, so it didn't fail while restarting
+    #[cfg(target_os = "linux")]
+    pub(crate) fn restarting(&self) -> bool {
+        let running = self.statistics.health() == MonitorHealth::Running;
+        self.statistics.set_health(MonitorHealth::Restarting);
+        self.statistics.restarts.fetch_add(1, Ordering::Relaxed);
+        running
+    }
+
+    /// Records that the driver is running
+    #[cfg(target_os = "linux")]
+    pub(crate) fn running(&self) {
+        self.statistics.set_health(MonitorHealth::Running);
+    }
+
+    /// Records the update derived from state and emits it when it differs from the previously emitted update.
+    ///
+    /// cause is the [Event] which lead to the current state if it is known.
+    /// When debouncing, the update is only emitted by [`Emitter::flush`] after it was stable for the debounce period of its direction.
+    /// When probing, the internet connectivity is probed again when the paths to it changed and the update reflects what was verified so far.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the receive end of the channel was dropped.
+    pub(crate) fn update(&mut self, state: &Interfaces, cause: Option<Event>) -> Result<(), Error> {
+        if let Sender::Events(ref tx) = self.sender {
+            if let Some(ref event) = cause {
+                debug!("emit event {:?}", event);
+                tx.unbounded_send(event.clone())
+                    .map_err(|_closed| Error::Closed)?;
+            }
+        }
+        if cause.is_some() {
+            self.cause = cause;
+        }
+
+        self.routed = state.connectivity();
+        if let Sender::Snapshots(_) = self.sender {
+            self.routed_snapshots = state.snapshots();
+        }
+        if let Some(ref mut prober) = self.prober {
+            prober.request(state.paths());
+        }
+        self.observe(Instant::now())
+    }
+
+    /// Records the update derived from the routed state and what the prober verified at now, and emits it when it doesn't need to be debounced.
+    ///
+    /// Whether the update raises or lowers the connectivity decides which debounce period applies.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the receive end of the channel was dropped.
+    fn observe(&mut self, now: Instant) -> Result<(), Error> {
+        let connectivity = self
+            .prober
+            .as_ref()
+            .map_or(self.routed, |prober| prober.verify(self.routed));
+        let connectivity_changed = self.connectivity.observe(connectivity);
+        let changed = match self.sender {
+            Sender::Connectivity(_) | Sender::Events(_) | Sender::Watch(..) => connectivity_changed,
+            Sender::Snapshots(_) => {
+                let mut snapshots = self.routed_snapshots.clone();
+                if let Some(ref prober) = self.prober {
+                    for snapshot in &mut snapshots {
+                        prober.verify_snapshot(snapshot);
+                    }
+                }
+                self.snapshots.observe(snapshots)
+            }
+        };
+
+        let period = self
+            .debounce
+            .period(self.connectivity.emitted, connectivity);
+        if period.is_zero() {
+            self.flush()
+        } else {
+            if changed {
+                self.deadline = now.checked_add(period);
+            }
+            Ok(())
+        }
+    }
+
+    /// Completes after a debounced update was stable long enough and is emitted or a probe completed and its result is recorded.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the receive end of the channel was dropped.
+    pub(crate) async fn idle(&mut self) -> Result<(), Error> {
+        let deadline = self.deadline;
+        let settled = async {
+            match deadline {
+                Some(instant) => sleep_until(instant).await,
+                None => future::pending().await,
+            }
+        };
+        let probed = async {
+            match self.prober {
+                Some(ref mut prober) => prober.probed().await,
+                None => future::pending().await,
+            }
+        };
+        let is_settled = select_biased! {
+            _ = settled.fuse() => true,
+            _ = probed.fuse() => false,
+        };
+        if is_settled {
+            debug!("emit settled state");
+            self.flush()
+        } else {
+            self.observe(Instant::now())
+        }
+    }
+
+    /// Emits the last recorded update without waiting for the debounce period when nothing was emitted yet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the receive end of the channel was dropped.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn flush_initial(&mut self) -> Result<(), Error> {
+        let emitted = match self.sender {
+            Sender::Connectivity(_) | Sender::Events(_) | Sender::Watch(..) => {
+                self.connectivity.emitted.is_some()
+            }
+            Sender::Snapshots(_) => self.snapshots.emitted.is_some(),
+        };
+        if emitted {
+            Ok(())
+        } else {
+            self.flush()
+        }
+    }
+
+    /// Emits the last recorded update when it differs from the previously emitted update.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the receive end of the channel was dropped.
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.deadline = None;
+        let cause = self.cause.take();
+        match self.sender {
+            Sender::Connectivity(ref tx) => {
+                if let Some((_, connectivity)) = self.connectivity.emit() {
+                    debug!("emit connectivity {:?}", connectivity);
+                    tx.unbounded_send(connectivity)
+                        .map_err(|_closed| Error::Closed)?;
+                }
+            }
+            Sender::Watch(ref tx, ref callbacks) => {
+                if let Some((emitted, connectivity)) = self.connectivity.emit() {
+                    // the initial connectivity always notifies, so receivers know it replaced the placeholder
+                    let old = if emitted.is_none() {
+                        tx.send_replace(connectivity)
+                    } else {
+                        let mut previous = connectivity;
+                        tx.send_if_modified(|current| {
+                            previous = *current;
+                            diff_assign(current, connectivity)
+                        });
+                        previous
+                    };
+                    if emitted.is_none() || old != connectivity {
+                        debug!("emit connectivity {:?}", connectivity);
+                    }
+                    if old != connectivity {
+                        callbacks.call(old, connectivity);
+                    }
+                }
+            }
+            Sender::Snapshots(ref tx) => {
+                // the connectivity decides which debounce period applies to the next update
+                self.connectivity.emit();
+                if let Some((_, snapshots)) = self.snapshots.emit() {
+                    debug!("emit snapshots {:?}", snapshots);
+                    tx.unbounded_send(snapshots)
+                        .map_err(|_closed| Error::Closed)?;
+                }
+            }
+            Sender::Events(ref tx) => {
+                if let Some((emitted, new)) = self.connectivity.emit() {
+                    let old = emitted.unwrap_or(Connectivity {
+                        ipv4: ConnectivityState::None,
+                        ipv6: ConnectivityState::None,
+                    });
+                    if old != new {
+                        let event = Event::ConnectivityChanged {
+                            old,
+                            new,
+                            cause: cause.map(Box::new),
+                        };
+                        debug!("emit event {:?}", event);
+                        tx.unbounded_send(event).map_err(|_closed| Error::Closed)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{AddressInfo, Filter, LinkInfo, Scope},
+        AddressState, LinkState,
+    };
+    use futures::{
+        channel::mpsc::{self, UnboundedReceiver},
+        FutureExt, StreamExt,
+    };
+    use std::net::{IpAddr, Ipv6Addr};
+
+    /// Create an [`Emitter`] of [Connectivity] updates debounced with debounce and the receive end of its channel
+    fn connectivity_emitter(debounce: Debounce) -> (Emitter, UnboundedReceiver<Connectivity>) {
+        let (tx, rx) = mpsc::unbounded();
+        let emitter = Emitter::new(
+            Sender::Connectivity(tx),
+            Box::pin(future::pending()),
+            debounce,
+            None,
+            Arc::default(),
+        );
+        (emitter, rx)
+    }
+
+    /// Create a [Connectivity] with ipv4 and without ipv6 connectivity
+    const fn ipv4(state: ConnectivityState) -> Connectivity {
+        Connectivity {
+            ipv4: state,
+            ipv6: ConnectivityState::None,
+        }
+    }
+
+    /// Records that the routes lead to connectivity at now
+    fn route(emitter: &mut Emitter, connectivity: Connectivity, now: Instant) {
+        emitter.routed = connectivity;
+        emitter.observe(now).unwrap();
+    }
+
+    /// Lets the clock pass until now, emitting the debounced update like [`Emitter::idle`] does when its deadline passed
+    fn advance(emitter: &mut Emitter, now: Instant) {
+        if emitter.deadline.map_or(false, |deadline| deadline <= now) {
+            emitter.flush().unwrap();
+        }
+    }
+
+    /// Get the updates that were emitted so far
+    fn received(rx: &mut UnboundedReceiver<Connectivity>) -> Vec<Connectivity> {
+        let mut updates = Vec::new();
+        while let Some(Some(update)) = rx.next().now_or_never() {
+            updates.push(update);
+        }
+        updates
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn backoff_doubles_up_to_the_maximum() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(5),
+        };
+        let delays: Vec<Duration> = (0..6).map(|attempt| backoff.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 4000, 5000, 5000].map(Duration::from_millis)
+        );
+        // the factor or the delay overflowing is capped as well
+        assert_eq!(backoff.delay(40), backoff.max);
+        assert_eq!(backoff.delay(u32::MAX), backoff.max);
+
+        let immediate = Backoff {
+            initial: Duration::ZERO,
+            max: Duration::from_secs(5),
+        };
+        assert_eq!(immediate.delay(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn period_depends_on_the_direction() {
+        let debounce = Debounce {
+            upgrade: Duration::from_secs(1),
+            downgrade: Duration::from_secs(5),
+        };
+        let network = ipv4(ConnectivityState::Network);
+        let internet = ipv4(ConnectivityState::Internet);
+        assert_eq!(debounce.period(None, internet), debounce.upgrade);
+        assert_eq!(debounce.period(Some(network), internet), debounce.upgrade);
+        assert_eq!(debounce.period(Some(internet), internet), debounce.upgrade);
+        assert_eq!(debounce.period(Some(internet), network), debounce.downgrade);
+        // lowering either ip type is a downgrade
+        let mixed = Connectivity {
+            ipv4: ConnectivityState::Internet,
+            ipv6: ConnectivityState::LinkLocal,
+        };
+        let ipv6_network = Connectivity {
+            ipv4: ConnectivityState::Internet,
+            ipv6: ConnectivityState::Network,
+        };
+        assert_eq!(
+            debounce.period(Some(ipv6_network), mixed),
+            debounce.downgrade
+        );
+    }
+
+    #[test]
+    fn upgrade_is_emitted_immediately() {
+        let (mut emitter, mut rx) = connectivity_emitter(Debounce {
+            upgrade: Duration::ZERO,
+            downgrade: Duration::from_secs(5),
+        });
+        let start = Instant::now();
+        route(&mut emitter, ipv4(ConnectivityState::Network), start);
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Network)]);
+        route(
+            &mut emitter,
+            ipv4(ConnectivityState::Internet),
+            start + Duration::from_millis(10),
+        );
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Internet)]);
+        assert_eq!(emitter.deadline, None);
+    }
+
+    #[test]
+    fn downgrade_is_delayed() {
+        let (mut emitter, mut rx) = connectivity_emitter(Debounce {
+            upgrade: Duration::ZERO,
+            downgrade: Duration::from_secs(5),
+        });
+        let start = Instant::now();
+        route(&mut emitter, ipv4(ConnectivityState::Internet), start);
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Internet)]);
+
+        let lost = start + Duration::from_secs(1);
+        route(&mut emitter, ipv4(ConnectivityState::Network), lost);
+        assert_eq!(received(&mut rx), vec![]);
+        assert_eq!(emitter.deadline, Some(lost + Duration::from_secs(5)));
+        advance(&mut emitter, lost + Duration::from_secs(4));
+        assert_eq!(received(&mut rx), vec![]);
+        advance(&mut emitter, lost + Duration::from_secs(5));
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Network)]);
+        assert_eq!(emitter.deadline, None);
+    }
+
+    #[test]
+    fn flap_inside_the_period_is_suppressed() {
+        let (mut emitter, mut rx) = connectivity_emitter(Debounce {
+            upgrade: Duration::ZERO,
+            downgrade: Duration::from_secs(5),
+        });
+        let start = Instant::now();
+        route(&mut emitter, ipv4(ConnectivityState::Internet), start);
+        received(&mut rx);
+
+        route(
+            &mut emitter,
+            ipv4(ConnectivityState::Network),
+            start + Duration::from_secs(1),
+        );
+        route(
+            &mut emitter,
+            ipv4(ConnectivityState::Internet),
+            start + Duration::from_secs(2),
+        );
+        advance(&mut emitter, start + Duration::from_secs(10));
+        assert_eq!(received(&mut rx), vec![]);
+
+        // with a debounced upgrade the flap restarts the period and settles on what was emitted
+        let (mut debounced, mut debounced_rx) = connectivity_emitter(Debounce {
+            upgrade: Duration::from_secs(2),
+            downgrade: Duration::from_secs(2),
+        });
+        route(&mut debounced, ipv4(ConnectivityState::Internet), start);
+        debounced.flush().unwrap();
+        received(&mut debounced_rx);
+        route(
+            &mut debounced,
+            ipv4(ConnectivityState::Network),
+            start + Duration::from_secs(1),
+        );
+        let returned = start + Duration::from_secs(2);
+        route(&mut debounced, ipv4(ConnectivityState::Internet), returned);
+        assert_eq!(debounced.deadline, Some(returned + Duration::from_secs(2)));
+        advance(&mut debounced, returned + Duration::from_secs(2));
+        assert_eq!(received(&mut debounced_rx), vec![]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn initial_update_is_flushed_without_delay() {
+        let (mut emitter, mut rx) = connectivity_emitter(Debounce {
+            upgrade: Duration::from_secs(1),
+            downgrade: Duration::from_secs(1),
+        });
+        let start = Instant::now();
+        route(&mut emitter, ipv4(ConnectivityState::Internet), start);
+        assert_eq!(received(&mut rx), vec![]);
+        emitter.flush_initial().unwrap();
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Internet)]);
+        assert_eq!(emitter.deadline, None);
+
+        // once something was emitted, later updates are debounced again
+        route(
+            &mut emitter,
+            ipv4(ConnectivityState::Network),
+            start + Duration::from_secs(1),
+        );
+        emitter.flush_initial().unwrap();
+        assert_eq!(received(&mut rx), vec![]);
+        advance(&mut emitter, start + Duration::from_secs(2));
+        assert_eq!(received(&mut rx), vec![ipv4(ConnectivityState::Network)]);
+    }
+
+    /// Create the state of an interface that is up with a global ipv6 address in address_state of which the lifetimes started at now
+    fn addressed(now: Instant, address_state: AddressState) -> Interfaces {
+        let mut state = Interfaces::new(Filter::default());
+        state.add_link(LinkInfo {
+            index: 2,
+            name: Some("eth0".to_owned()),
+            loopback: false,
+            state: LinkState::Up,
+            mac: None,
+            mtu: None,
+            kind: None,
+            bridge_port: false,
+        });
+        state.add_address(&AddressInfo {
+            index: 2,
+            address: IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            state: address_state,
+            scope: Scope::Global,
+            preferred_until: now.checked_add(Duration::from_secs(14400)),
+            valid_until: now.checked_add(Duration::from_secs(86400)),
+            permanent: false,
+        });
+        state
+    }
+
+    #[test]
+    fn callbacks_are_called_in_registration_order_until_unregistered() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name: &'static str| -> Arc<Callback> {
+            let recorded = Arc::clone(&calls);
+            Arc::new(move |old, new| {
+                recorded.lock().unwrap().push((name, old.ipv4, new.ipv4));
+            })
+        };
+        let callbacks = Callbacks::default();
+        let first = callbacks.register(recorder("first"));
+        callbacks.register(recorder("second"));
+        let (tx, rx) = watch::channel(ipv4(ConnectivityState::None));
+        let mut emitter = Emitter::new(
+            Sender::Watch(Arc::new(tx), callbacks.clone()),
+            Box::pin(future::pending()),
+            Debounce::default(),
+            None,
+            Arc::default(),
+        );
+        let start = Instant::now();
+
+        route(&mut emitter, ipv4(ConnectivityState::Network), start);
+        assert_eq!(*rx.borrow(), ipv4(ConnectivityState::Network));
+        assert_eq!(
+            calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [
+                ("first", ConnectivityState::None, ConnectivityState::Network),
+                (
+                    "second",
+                    ConnectivityState::None,
+                    ConnectivityState::Network
+                ),
+            ]
+        );
+
+        // an unchanged connectivity doesn't call the callbacks
+        route(&mut emitter, ipv4(ConnectivityState::Network), start);
+        assert!(calls.lock().unwrap().is_empty());
+
+        callbacks.unregister(first);
+        route(&mut emitter, ipv4(ConnectivityState::Internet), start);
+        assert_eq!(
+            calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [(
+                "second",
+                ConnectivityState::Network,
+                ConnectivityState::Internet
+            )]
+        );
+    }
+
+    #[test]
+    fn callbacks_can_register_callbacks_while_called() {
+        let callbacks = Callbacks::default();
+        let registering = callbacks.clone();
+        let called = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&called);
+        callbacks.register(Arc::new(move |_, _| {
+            let nested = Arc::clone(&counter);
+            registering.register(Arc::new(move |_, _| {
+                nested.fetch_add(1, Ordering::Relaxed);
+            }));
+        }));
+        let old = ipv4(ConnectivityState::None);
+        callbacks.call(old, ipv4(ConnectivityState::Network));
+        // the callback registered during the call is only called for the next change
+        assert_eq!(called.load(Ordering::Relaxed), 0);
+        callbacks.call(old, ipv4(ConnectivityState::Internet));
+        assert_eq!(called.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn refreshed_lifetimes_are_not_emitted() {
+        let (tx, mut rx) = mpsc::unbounded();
+        let mut emitter = Emitter::new(
+            Sender::Snapshots(tx),
+            Box::pin(future::pending()),
+            Debounce::default(),
+            None,
+            Arc::default(),
+        );
+        let start = Instant::now();
+        emitter
+            .update(&addressed(start, AddressState::Preferred), None)
+            .unwrap();
+        let initial = rx.next().now_or_never().unwrap().unwrap();
+        assert_eq!(
+            initial[0].ipv6.addresses[0].preferred_until,
+            start.checked_add(Duration::from_secs(14400))
+        );
+
+        let refreshed = start + Duration::from_secs(600);
+        emitter
+            .update(&addressed(refreshed, AddressState::Preferred), None)
+            .unwrap();
+        assert!(rx.next().now_or_never().is_none());
+
+        // the next update that changes the interfaces has the refreshed lifetimes
+        let deprecated = addressed(refreshed, AddressState::Deprecated);
+        emitter.update(&deprecated, None).unwrap();
+        let changed = rx.next().now_or_never().unwrap().unwrap();
+        let address = changed[0].ipv6.addresses[0];
+        assert_eq!(address.state, AddressState::Deprecated);
+        assert!(!address.usable);
+        assert_eq!(
+            address.valid_until,
+            refreshed.checked_add(Duration::from_secs(86400))
+        );
+    }
+}
@@ -2,54 +2,366 @@
 
 //! The platform independent internal state for this crate
 
-use crate::{Connectivity, ConnectivityState};
+use crate::{
+    AddressScope, AddressSnapshot, AddressState, Connectivity, ConnectivityState, Event, Gateway,
+    InterfaceSnapshot, IpSnapshot, LinkState,
+};
 use core::cmp::max;
 use std::{
     collections::{HashMap, HashSet},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Instant,
 };
 
 /// Represents an interface index.
 type InterfaceIndex = u32;
+/// Represents an interface name if it is known
+type Name = Option<String>;
 /// Boolean indicating an interface is a loopback device
 type LoopBack = bool;
-/// Boolean indicating an interface has a carrier
-type Carrier = bool;
 /// Represents a route priority.
 type Priority = u32;
 
 /// Required information for links
-pub type LinkInfo = (InterfaceIndex, LoopBack, Carrier);
+#[derive(Debug)]
+pub struct LinkInfo {
+    /// The index of the interface
+    pub index: InterfaceIndex,
+    /// The name of the interface if it is known
+    pub name: Name,
+    /// Whether the interface is a loopback device
+    pub loopback: LoopBack,
+    /// The operational state of the interface
+    pub state: LinkState,
+    /// The hardware address of the interface if it is known
+    pub mac: Option<Vec<u8>>,
+    /// The maximum transmission unit of the interface if it is known
+    pub mtu: Option<u32>,
+    /// The kind of the interface if it isn't a physical device
+    pub kind: Option<String>,
+    /// Whether the interface is a port of a bridge
+    pub bridge_port: bool,
+}
+/// The scope in which an address is valid as reported by the system
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Scope {
+    /// The address is valid everywhere
+    Global,
+    /// The address is only valid on the link it is assigned to
+    Link,
+    /// The address is only valid on this host
+    Host,
+}
 /// Required information for addresses
-pub type AddressInfo = (InterfaceIndex, IpAddr);
+#[derive(Debug)]
+pub struct AddressInfo {
+    /// The index of the interface the address is assigned to
+    pub index: InterfaceIndex,
+    /// The address
+    pub address: IpAddr,
+    /// The duplicate address detection state of the address
+    pub state: AddressState,
+    /// The scope of the address
+    pub scope: Scope,
+    /// When the preferred lifetime of the address expires, none when it never expires
+    pub preferred_until: Option<Instant>,
+    /// When the valid lifetime of the address expires, none when it never expires
+    pub valid_until: Option<Instant>,
+    /// Whether the address is permanent, like a statically configured address
+    pub permanent: bool,
+}
+/// The gateway of a default route of an ip type, none when the route has no gateway of that ip type
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RouteGateway {
+    /// The gateway of an ipv4 default route
+    V4(Option<Ipv4Addr>),
+    /// The gateway of an ipv6 default route
+    V6(Option<Ipv6Addr>),
+}
+impl RouteGateway {
+    /// Get the address of the gateway
+    fn address(self) -> Option<IpAddr> {
+        match self {
+            Self::V4(address) => address.map(IpAddr::V4),
+            Self::V6(address) => address.map(IpAddr::V6),
+        }
+    }
+
+    /// Whether the gateway belongs to an ipv6 default route
+    const fn is_ipv6(self) -> bool {
+        matches!(self, Self::V6(_))
+    }
+}
 /// Required information for routes
-pub type RouteInfo = (InterfaceIndex, IpAddr, Priority);
+pub type RouteInfo = (InterfaceIndex, RouteGateway, Priority);
+
+/// An interface through which an ip type reaches the internet according to the routes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Path {
+    /// The index of the interface
+    pub index: InterfaceIndex,
+    /// The usable addresses of the interface in ascending order
+    pub addresses: Vec<IpAddr>,
+    /// The gateways of the default routes of the interface with their priority in ascending order
+    pub gateways: Vec<(Option<IpAddr>, Priority)>,
+}
+
+/// Decides which interfaces and ip types contribute to the state.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Filter {
+    /// Whether loopback devices contribute
+    pub loopback: bool,
+    /// The name patterns of the interfaces that contribute, all interfaces contribute when this is empty
+    pub include: HashSet<String>,
+    /// The name patterns of the interfaces that never contribute
+    pub exclude: HashSet<String>,
+    /// The kinds of the interfaces that contribute, all kinds contribute when this is empty
+    pub include_kinds: HashSet<String>,
+    /// The kinds of the interfaces that never contribute
+    pub exclude_kinds: HashSet<String>,
+    /// Whether ports of a bridge contribute
+    pub bridge_ports: bool,
+    /// Whether permanent addresses contribute
+    pub permanent_addresses: bool,
+    /// Whether ipv4 contributes
+    pub ipv4: bool,
+    /// Whether ipv6 contributes
+    pub ipv6: bool,
+}
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            loopback: false,
+            include: HashSet::new(),
+            exclude: HashSet::new(),
+            include_kinds: HashSet::new(),
+            exclude_kinds: HashSet::new(),
+            bridge_ports: true,
+            permanent_addresses: true,
+            ipv4: true,
+            ipv6: true,
+        }
+    }
+}
+impl Filter {
+    /// Whether an interface should be ignored
+    fn ignores_link(&self, link: &LinkInfo) -> bool {
+        let name = link.name.as_deref();
+        let kind = link.kind.as_deref();
+        let included = (self.include.is_empty() || matches_any(&self.include, name))
+            && (self.include_kinds.is_empty()
+                || kind.map_or(false, |known| self.include_kinds.contains(known)));
+        let excluded = matches_any(&self.exclude, name)
+            || kind.map_or(false, |known| self.exclude_kinds.contains(known))
+            || (link.bridge_port && !self.bridge_ports);
+        (link.loopback && !self.loopback) || !included || excluded
+    }
+
+    /// Whether an interface of which no link information is known yet should be ignored
+    fn ignores_unknown_link(&self) -> bool {
+        !self.include.is_empty() || !self.include_kinds.is_empty()
+    }
+
+    /// Whether the ip type of an address should be ignored
+    const fn ignores_ip_type(&self, address: &IpAddr) -> bool {
+        match *address {
+            IpAddr::V4(_) => !self.ipv4,
+            IpAddr::V6(_) => !self.ipv6,
+        }
+    }
+
+    /// Whether the ip type of a default route should be ignored
+    const fn ignores_gateway(&self, gateway: RouteGateway) -> bool {
+        if gateway.is_ipv6() {
+            !self.ipv6
+        } else {
+            !self.ipv4
+        }
+    }
+
+    /// Whether an address should be ignored
+    const fn ignores_address(&self, address_info: &AddressInfo) -> bool {
+        self.ignores_ip_type(&address_info.address)
+            || (address_info.permanent && !self.permanent_addresses)
+    }
+}
+
+/// Whether name is known and matches one of patterns
+fn matches_any(patterns: &HashSet<String>, name: Option<&str>) -> bool {
+    name.map_or(false, |known| {
+        let name_characters: Vec<char> = known.chars().collect();
+        patterns.iter().any(|pattern| {
+            let pattern_characters: Vec<char> = pattern.chars().collect();
+            glob_matches(&pattern_characters, &name_characters)
+        })
+    })
+}
+
+/// Whether name matches pattern, where `*` matches any sequence of characters and `?` matches any single character
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|skip| {
+            name.get(skip..)
+                .map_or(false, |tail| glob_matches(rest, tail))
+        }),
+        Some((&'?', rest)) => name
+            .split_first()
+            .map_or(false, |(_, tail)| glob_matches(rest, tail)),
+        Some((literal, rest)) => name.split_first().map_or(false, |(character, tail)| {
+            character == literal && glob_matches(rest, tail)
+        }),
+    }
+}
+
+/// Records the properties of a single address.
+#[derive(Debug)]
+struct Address {
+    /// The duplicate address detection state of the address
+    state: AddressState,
+    /// The scope of the address
+    scope: AddressScope,
+    /// When the preferred lifetime of the address expires
+    preferred_until: Option<Instant>,
+    /// When the valid lifetime of the address expires
+    valid_until: Option<Instant>,
+}
+impl Address {
+    /// Whether the address can be the source of traffic
+    fn usable(&self) -> bool {
+        let state_usable = matches!(
+            self.state,
+            AddressState::Optimistic | AddressState::Preferred
+        );
+        state_usable && self.scope != AddressScope::Host
+    }
+}
+
+/// Classify address by its range, or by scope when the system limits it to the host or link
+const fn classify(address: IpAddr, scope: Scope) -> AddressScope {
+    match (scope, address) {
+        (Scope::Host, _) => AddressScope::Host,
+        (_, IpAddr::V4(ipv4_address)) if ipv4_address.is_loopback() => AddressScope::Host,
+        (_, IpAddr::V6(ipv6_address)) if ipv6_address.is_loopback() => AddressScope::Host,
+        (Scope::Link, _) => AddressScope::LinkLocal,
+        (_, IpAddr::V4(ipv4_address)) if ipv4_address.is_link_local() => AddressScope::LinkLocal,
+        (_, IpAddr::V6(ipv6_address)) if ipv6_address.segments()[0] & 0xffc0 == 0xfe80 => {
+            AddressScope::LinkLocal
+        }
+        (_, IpAddr::V6(ipv6_address)) if ipv6_address.segments()[0] & 0xfe00 == 0xfc00 => {
+            AddressScope::UniqueLocal
+        }
+        (_, IpAddr::V4(ipv4_address)) if ipv4_address.is_private() => AddressScope::Private,
+        (Scope::Global, _) => AddressScope::Global,
+    }
+}
 
 /// Records the state for a specific ip type.
 #[derive(Debug)]
 struct AddressGateway<T> {
     /// The addresses associated with this [AddressGateway]
-    addresses: HashSet<T>,
-    /// The gateways associated with this [AddressGateway]
-    gateways: HashSet<(T, Priority)>,
+    addresses: HashMap<T, Address>,
+    /// The gateways associated with this [AddressGateway], none for a default route without a gateway
+    gateways: HashSet<(Option<T>, Priority)>,
 }
 impl<T> AddressGateway<T> {
     /// Convert to [`ConnectivityState`]
-    fn connectivity_state(&self, up: bool) -> ConnectivityState {
-        let address = !self.addresses.is_empty();
+    fn connectivity_state(&self, link_state: LinkState) -> ConnectivityState {
+        let up = link_state == LinkState::Up;
+        let scope = self
+            .addresses
+            .values()
+            .filter(|properties| properties.usable())
+            .map(|properties| properties.scope)
+            .max();
         let gateway = !self.gateways.is_empty();
-        match (up, address, gateway) {
-            (false, _, _) | (true, false, _) => ConnectivityState::None,
-            (true, true, false) => ConnectivityState::Network,
-            (true, true, true) => ConnectivityState::Internet,
+        match (up, scope, gateway) {
+            (false, _, _) | (true, None, _) => ConnectivityState::None,
+            // without a routable address a gateway can't be used
+            (true, Some(widest), _) if widest <= AddressScope::LinkLocal => {
+                ConnectivityState::LinkLocal
+            }
+            (true, Some(_), false) => ConnectivityState::Network,
+            (true, Some(_), true) => ConnectivityState::Internet,
+        }
+    }
+
+    /// Convert to a [Path] when the internet is reachable through it
+    fn path(&self, index: InterfaceIndex, link_state: LinkState) -> Option<Path>
+    where
+        T: Ord + Copy + Into<IpAddr>,
+    {
+        if self.connectivity_state(link_state) != ConnectivityState::Internet {
+            return None;
+        }
+        let mut addresses: Vec<IpAddr> = self
+            .addresses
+            .iter()
+            .filter(|&(_, properties)| properties.usable())
+            .map(|(&address, _)| address.into())
+            .collect();
+        addresses.sort_unstable();
+        let mut gateways: Vec<(Option<IpAddr>, Priority)> = self
+            .gateways
+            .iter()
+            .map(|&(address, priority)| (address.map(Into::into), priority))
+            .collect();
+        gateways.sort_unstable();
+        Some(Path {
+            index,
+            addresses,
+            gateways,
+        })
+    }
+
+    /// Convert to [`IpSnapshot`]
+    fn snapshot(&self, link_state: LinkState) -> IpSnapshot<T>
+    where
+        T: Ord + Copy,
+    {
+        let mut addresses: Vec<AddressSnapshot<T>> = self
+            .addresses
+            .iter()
+            .map(|(&address, properties)| AddressSnapshot {
+                address,
+                state: properties.state,
+                scope: properties.scope,
+                preferred_until: properties.preferred_until,
+                valid_until: properties.valid_until,
+                usable: properties.usable(),
+            })
+            .collect();
+        addresses.sort_unstable_by_key(|snapshot| snapshot.address);
+        let mut gateways: Vec<Gateway<T>> = self
+            .gateways
+            .iter()
+            .map(|&(address, priority)| Gateway { address, priority })
+            .collect();
+        gateways.sort_unstable_by_key(|gateway| (gateway.priority, gateway.address));
+        IpSnapshot {
+            addresses,
+            gateways,
+            state: self.connectivity_state(link_state),
+            portal: None,
         }
     }
 }
 /// Records the complete state for a single interface.
 #[derive(Debug)]
 struct Interface {
-    /// Whether the interface is able to communicate with the network
-    up: bool,
+    /// The name of the interface
+    name: Name,
+    /// The hardware address of the interface
+    mac: Option<Vec<u8>>,
+    /// The maximum transmission unit of the interface
+    mtu: Option<u32>,
+    /// The kind of the interface
+    kind: Option<String>,
+    /// Whether the interface is ignored by the [Filter]
+    ignored: bool,
+    /// The operational state of the interface, it is only able to communicate with the network when it is [`LinkState::Up`]
+    state: LinkState,
     /// The ipv4 [AddressGateway]  for the interface
     ipv4: AddressGateway<Ipv4Addr>,
     /// The ipv6 [AddressGateway]  for the interface
@@ -57,15 +369,20 @@ struct Interface {
 }
 impl Interface {
     /// Create a new [`Interface`] instance
-    fn new(up: bool) -> Self {
+    fn new(ignored: bool) -> Self {
         Self {
-            up,
+            name: None,
+            mac: None,
+            mtu: None,
+            kind: None,
+            ignored,
+            state: LinkState::Down,
             ipv4: AddressGateway {
-                addresses: HashSet::new(),
+                addresses: HashMap::new(),
                 gateways: HashSet::new(),
             },
             ipv6: AddressGateway {
-                addresses: HashSet::new(),
+                addresses: HashMap::new(),
                 gateways: HashSet::new(),
             },
         }
@@ -74,101 +391,486 @@ impl Interface {
     /// Convert to [Connectivity]
     fn connectivity(&self) -> Connectivity {
         Connectivity {
-            ipv4: self.ipv4.connectivity_state(self.up),
-            ipv6: self.ipv6.connectivity_state(self.up),
+            ipv4: self.ipv4.connectivity_state(self.state),
+            ipv6: self.ipv6.connectivity_state(self.state),
+        }
+    }
+
+    /// Convert to [`InterfaceSnapshot`]
+    fn snapshot(&self, index: InterfaceIndex) -> InterfaceSnapshot {
+        InterfaceSnapshot {
+            index,
+            name: self.name.clone(),
+            mac: self.mac.clone(),
+            mtu: self.mtu,
+            kind: self.kind.clone(),
+            link_state: self.state,
+            ipv4: self.ipv4.snapshot(self.state),
+            ipv6: self.ipv6.snapshot(self.state),
         }
     }
 }
 
 /// Records the complete state for all interfaces.
 pub struct Interfaces {
+    /// The [Filter] applied to new entries
+    filter: Filter,
     /// The mapping between [InterfaceIndex] and [Interface]
     state: HashMap<InterfaceIndex, Interface>,
 }
 impl Interfaces {
     /// Create a new [`Interfaces`] instance
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(filter: Filter) -> Self {
         Self {
+            filter,
             state: HashMap::new(),
         }
     }
 
+    /// Get the [Interface] for index, creating it when it doesn't exist yet
+    fn entry(&mut self, index: InterfaceIndex) -> &mut Interface {
+        let filter = &self.filter;
+        self.state
+            .entry(index)
+            .or_insert_with(|| Interface::new(filter.ignores_unknown_link()))
+    }
+
     /// Convert to [Connectivity]
     pub(crate) fn connectivity(&self) -> Connectivity {
-        self.state.values().fold(
-            Connectivity {
-                ipv4: ConnectivityState::None,
-                ipv6: ConnectivityState::None,
-            },
-            |mut accumulator, interface_state| {
-                let interface_connectivity = interface_state.connectivity();
-                accumulator.ipv4 = max(accumulator.ipv4, interface_connectivity.ipv4);
-                accumulator.ipv6 = max(accumulator.ipv6, interface_connectivity.ipv6);
-                accumulator
-            },
-        )
+        self.state
+            .values()
+            .filter(|interface| !interface.ignored)
+            .fold(
+                Connectivity {
+                    ipv4: ConnectivityState::None,
+                    ipv6: ConnectivityState::None,
+                },
+                |mut accumulator, interface_state| {
+                    let interface_connectivity = interface_state.connectivity();
+                    accumulator.ipv4 = max(accumulator.ipv4, interface_connectivity.ipv4);
+                    accumulator.ipv6 = max(accumulator.ipv6, interface_connectivity.ipv6);
+                    accumulator
+                },
+            )
+    }
+
+    /// Convert to a list of [`InterfaceSnapshot`] ordered by interface index
+    pub(crate) fn snapshots(&self) -> Vec<InterfaceSnapshot> {
+        let mut snapshots: Vec<InterfaceSnapshot> = self
+            .state
+            .iter()
+            .filter(|&(_, interface)| !interface.ignored)
+            .map(|(&index, interface)| interface.snapshot(index))
+            .collect();
+        snapshots.sort_unstable_by_key(|snapshot| snapshot.index);
+        snapshots
     }
 
-    /// Adds a link entry
-    pub(crate) fn add_link(&mut self, link: LinkInfo) {
-        let (index, loop_back, carrier) = link;
-        if !loop_back {
-            let s = self
-                .state
-                .entry(index)
-                .or_insert_with(|| Interface::new(false));
-            s.up = carrier;
+    /// Get the ipv4 and ipv6 [Path]s to the internet, ordered by interface index
+    pub(crate) fn paths(&self) -> (Vec<Path>, Vec<Path>) {
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for (&index, interface) in self
+            .state
+            .iter()
+            .filter(|&(_, interface)| !interface.ignored)
+        {
+            ipv4.extend(interface.ipv4.path(index, interface.state));
+            ipv6.extend(interface.ipv6.path(index, interface.state));
+        }
+        ipv4.sort_unstable_by_key(|path| path.index);
+        ipv6.sort_unstable_by_key(|path| path.index);
+        (ipv4, ipv6)
+    }
+
+    /// Adds a link entry, updating the name, hardware address, maximum transmission unit and kind of a known interface and whether the [Filter] ignores it
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the interface became or stopped being [`LinkState::Up`], or otherwise when the interface was renamed
+    pub(crate) fn add_link(&mut self, link: LinkInfo) -> Option<Event> {
+        let ignored = self.filter.ignores_link(&link);
+        let index = link.index;
+        let s = self.entry(index);
+        let old_name = match link.name {
+            Some(name) => s.name.replace(name),
+            None => None,
+        };
+        if link.mac.is_some() {
+            s.mac = link.mac;
+        }
+        if link.mtu.is_some() {
+            s.mtu = link.mtu;
+        }
+        s.kind = link.kind;
+        s.ignored = ignored;
+        let was_up = s.state == LinkState::Up;
+        let up = link.state == LinkState::Up;
+        s.state = link.state;
+        if s.ignored {
+            return None;
+        }
+        let name = s.name.clone();
+        match (was_up, up) {
+            (false, true) => Some(Event::LinkUp { index, name }),
+            (true, false) => Some(Event::LinkDown { index, name }),
+            _ => match (old_name, name) {
+                (Some(old), Some(new)) if old != new => {
+                    Some(Event::LinkRenamed { index, old, new })
+                }
+                _ => None,
+            },
         }
     }
     /// Removes a link entry
-    pub(crate) fn remove_link(&mut self, link: LinkInfo) {
-        let (index, _, _) = link;
-        self.state.remove(&index);
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the removed interface was [`LinkState::Up`]
+    #[cfg(target_os = "linux")]
+    pub(crate) fn remove_link(&mut self, link: &LinkInfo) -> Option<Event> {
+        let index = link.index;
+        let interface = self.state.remove(&index)?;
+        (interface.state == LinkState::Up && !interface.ignored).then_some(Event::LinkDown {
+            index,
+            name: interface.name,
+        })
     }
 
-    /// Adds an address entry
-    pub(crate) fn add_address(&mut self, address_info: AddressInfo) {
-        let (index, address) = address_info;
-        let entry = self
-            .state
-            .entry(index)
-            .or_insert_with(|| Interface::new(false));
-        match address {
-            IpAddr::V4(ipv4_address) => entry.ipv4.addresses.insert(ipv4_address),
-            IpAddr::V6(ipv6_address) => entry.ipv6.addresses.insert(ipv6_address),
+    /// Adds an address entry or updates the properties of a known address
+    ///
+    /// A known address that the [Filter] ignores now, like an address that became permanent, is removed.
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the address became usable or stopped being usable
+    pub(crate) fn add_address(&mut self, address_info: &AddressInfo) -> Option<Event> {
+        let index = address_info.index;
+        let address = address_info.address;
+        if self.filter.ignores_address(address_info) {
+            return self.forget_address(index, address);
+        }
+        let properties = Address {
+            state: address_info.state,
+            scope: classify(address, address_info.scope),
+            preferred_until: address_info.preferred_until,
+            valid_until: address_info.valid_until,
+        };
+        let usable = properties.usable();
+        let entry = self.entry(index);
+        let previous = match address {
+            IpAddr::V4(ipv4_address) => entry.ipv4.addresses.insert(ipv4_address, properties),
+            IpAddr::V6(ipv6_address) => entry.ipv6.addresses.insert(ipv6_address, properties),
         };
+        let was_usable = previous.as_ref().map_or(false, Address::usable);
+        if entry.ignored {
+            return None;
+        }
+        let name = entry.name.clone();
+        match (was_usable, usable) {
+            (false, true) => Some(Event::AddressAdded {
+                index,
+                name,
+                address,
+            }),
+            (true, false) => Some(Event::AddressRemoved {
+                index,
+                name,
+                address,
+            }),
+            _ => None,
+        }
     }
     /// Removes an address entry
-    pub(crate) fn remove_address(&mut self, address_info: AddressInfo) {
-        let (index, address) = address_info;
-        self.state.entry(index).and_modify(|entry| {
-            match address {
-                IpAddr::V4(ipv4_address) => entry.ipv4.addresses.remove(&ipv4_address),
-                IpAddr::V6(ipv6_address) => entry.ipv6.addresses.remove(&ipv6_address),
-            };
-        });
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the address was usable
+    #[cfg(target_os = "linux")]
+    pub(crate) fn remove_address(&mut self, address_info: &AddressInfo) -> Option<Event> {
+        self.forget_address(address_info.index, address_info.address)
+    }
+    /// Removes the entry of address from the interface with index
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the address was usable
+    fn forget_address(&mut self, index: InterfaceIndex, address: IpAddr) -> Option<Event> {
+        let entry = self.state.get_mut(&index)?;
+        let removed = match address {
+            IpAddr::V4(ipv4_address) => entry.ipv4.addresses.remove(&ipv4_address),
+            IpAddr::V6(ipv6_address) => entry.ipv6.addresses.remove(&ipv6_address),
+        };
+        (removed.map_or(false, |properties| properties.usable()) && !entry.ignored).then(|| {
+            Event::AddressRemoved {
+                index,
+                name: entry.name.clone(),
+                address,
+            }
+        })
     }
 
     /// Adds a default route entry
-    pub(crate) fn add_default_route(&mut self, route: RouteInfo) {
-        let (index, address, priority) = route;
-        let entry = self
-            .state
-            .entry(index)
-            .or_insert_with(|| Interface::new(false));
-        match address {
-            IpAddr::V4(ipv4_address) => entry.ipv4.gateways.insert((ipv4_address, priority)),
-            IpAddr::V6(ipv6_address) => entry.ipv6.gateways.insert((ipv6_address, priority)),
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the default route was not known yet
+    pub(crate) fn add_default_route(&mut self, route: RouteInfo) -> Option<Event> {
+        let (index, gateway, priority) = route;
+        if self.filter.ignores_gateway(gateway) {
+            return None;
+        }
+        let entry = self.entry(index);
+        let added = match gateway {
+            RouteGateway::V4(ipv4_address) => entry.ipv4.gateways.insert((ipv4_address, priority)),
+            RouteGateway::V6(ipv6_address) => entry.ipv6.gateways.insert((ipv6_address, priority)),
         };
+        (added && !entry.ignored).then(|| Event::DefaultRouteAdded {
+            index,
+            name: entry.name.clone(),
+            ipv6: gateway.is_ipv6(),
+            gateway: gateway.address(),
+            priority,
+        })
     }
     /// Removes a default route entry
-    pub(crate) fn remove_default_route(&mut self, route: RouteInfo) {
-        let (index, address, priority) = route;
-        self.state.entry(index).and_modify(|entry| {
-            match address {
-                IpAddr::V4(ipv4_address) => entry.ipv4.gateways.remove(&(ipv4_address, priority)),
-                IpAddr::V6(ipv6_address) => entry.ipv6.gateways.remove(&(ipv6_address, priority)),
-            };
-        });
+    ///
+    /// # Returns
+    ///
+    /// An [Event] when the default route was known
+    #[cfg(target_os = "linux")]
+    pub(crate) fn remove_default_route(&mut self, route: RouteInfo) -> Option<Event> {
+        let (index, gateway, priority) = route;
+        let entry = self.state.get_mut(&index)?;
+        let removed = match gateway {
+            RouteGateway::V4(ipv4_address) => entry.ipv4.gateways.remove(&(ipv4_address, priority)),
+            RouteGateway::V6(ipv6_address) => entry.ipv6.gateways.remove(&(ipv6_address, priority)),
+        };
+        (removed && !entry.ignored).then(|| Event::DefaultRouteRemoved {
+            index,
+            name: entry.name.clone(),
+            ipv6: gateway.is_ipv6(),
+            gateway: gateway.address(),
+            priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether name matches pattern
+    fn glob(pattern: &str, name: &str) -> bool {
+        let pattern_characters: Vec<char> = pattern.chars().collect();
+        let name_characters: Vec<char> = name.chars().collect();
+        glob_matches(&pattern_characters, &name_characters)
+    }
+
+    /// Create a set of patterns or kinds
+    fn set(items: &[&str]) -> HashSet<String> {
+        items.iter().map(|&item| item.to_owned()).collect()
+    }
+
+    /// Create the link information of an interface named name of kind
+    fn link(name: Option<&str>, kind: Option<&str>) -> LinkInfo {
+        LinkInfo {
+            index: 2,
+            name: name.map(str::to_owned),
+            loopback: false,
+            state: LinkState::Up,
+            mac: None,
+            mtu: None,
+            kind: kind.map(str::to_owned),
+            bridge_port: false,
+        }
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob("eth0", "eth0"));
+        assert!(!glob("eth0", "eth1"));
+        assert!(!glob("eth", "eth0"));
+        assert!(glob("eth*", "eth"));
+        assert!(glob("eth*", "eth0"));
+        assert!(!glob("eth*", "veth0"));
+        assert!(glob("*eth*", "veth0"));
+        assert!(glob("*", ""));
+        assert!(glob("wl?0", "wlp0"));
+        assert!(!glob("wl?0", "wl0"));
+        assert!(glob("docker?*", "docker0"));
+        assert!(!glob("docker?*", "docker"));
+        assert!(glob("", ""));
+        assert!(!glob("", "lo"));
+        assert!(glob("ö*", "östen"));
+    }
+
+    #[test]
+    fn matches_any_requires_a_known_name() {
+        let patterns = set(&["eth*", "wlan0"]);
+        assert!(matches_any(&patterns, Some("eth1")));
+        assert!(matches_any(&patterns, Some("wlan0")));
+        assert!(!matches_any(&patterns, Some("wlan1")));
+        assert!(!matches_any(&patterns, None));
+        assert!(!matches_any(&HashSet::new(), Some("eth0")));
+    }
+
+    #[test]
+    fn exclude_takes_precedence_over_include() {
+        let filter = Filter {
+            include: set(&["eth*", "wl*"]),
+            exclude: set(&["eth1"]),
+            exclude_kinds: set(&["veth"]),
+            bridge_ports: false,
+            ..Filter::default()
+        };
+        assert!(!filter.ignores_link(&link(Some("eth0"), None)));
+        assert!(filter.ignores_link(&link(Some("eth1"), None)));
+        assert!(filter.ignores_link(&link(Some("eth2"), Some("veth"))));
+        assert!(filter.ignores_link(&link(Some("lo"), None)));
+        // the name of an interface must be known before it is included
+        assert!(filter.ignores_link(&link(None, None)));
+        assert!(filter.ignores_unknown_link());
+
+        let mut port = link(Some("wlan0"), None);
+        port.bridge_port = true;
+        assert!(filter.ignores_link(&port));
+        let bridge_ports = Filter {
+            bridge_ports: true,
+            ..filter
+        };
+        assert!(!bridge_ports.ignores_link(&port));
+    }
+
+    #[test]
+    fn include_kinds_require_a_known_kind() {
+        let filter = Filter {
+            include_kinds: set(&["wireguard", "veth"]),
+            exclude: set(&["wg1"]),
+            ..Filter::default()
+        };
+        assert!(!filter.ignores_link(&link(Some("wg0"), Some("wireguard"))));
+        assert!(filter.ignores_link(&link(Some("wg1"), Some("wireguard"))));
+        assert!(filter.ignores_link(&link(Some("eth0"), None)));
+        assert!(filter.ignores_link(&link(Some("br0"), Some("bridge"))));
+
+        let everything = Filter::default();
+        assert!(!everything.ignores_link(&link(Some("eth0"), None)));
+        assert!(!everything.ignores_unknown_link());
+        let mut loopback = link(Some("lo"), None);
+        loopback.loopback = true;
+        assert!(everything.ignores_link(&loopback));
+    }
+
+    /// Create the addresses and gateways of an ip type with preferred addresses in the system scope and default routes via gateways
+    fn address_gateway(
+        addresses: &[(Ipv4Addr, Scope)],
+        gateways: &[Ipv4Addr],
+    ) -> AddressGateway<Ipv4Addr> {
+        AddressGateway {
+            addresses: addresses
+                .iter()
+                .map(|&(address, scope)| {
+                    let properties = Address {
+                        state: AddressState::Preferred,
+                        scope: classify(IpAddr::V4(address), scope),
+                        preferred_until: None,
+                        valid_until: None,
+                    };
+                    (address, properties)
+                })
+                .collect(),
+            gateways: gateways.iter().map(|&gateway| (Some(gateway), 0)).collect(),
+        }
+    }
+
+    #[test]
+    fn addresses_are_classified_by_range_and_scope() {
+        let global = Ipv4Addr::new(198, 51, 100, 7);
+        let link_local = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        assert_eq!(
+            classify(IpAddr::V4(global), Scope::Global),
+            AddressScope::Global
+        );
+        assert_eq!(
+            classify(IpAddr::V4(global), Scope::Link),
+            AddressScope::LinkLocal
+        );
+        assert_eq!(
+            classify(IpAddr::V4(global), Scope::Host),
+            AddressScope::Host
+        );
+        assert_eq!(
+            classify(IpAddr::V4(Ipv4Addr::new(169, 254, 3, 4)), Scope::Global),
+            AddressScope::LinkLocal
+        );
+        assert_eq!(
+            classify(IpAddr::V6(link_local), Scope::Global),
+            AddressScope::LinkLocal
+        );
+        assert_eq!(
+            classify(IpAddr::V6(Ipv6Addr::LOCALHOST), Scope::Global),
+            AddressScope::Host
+        );
+        assert_eq!(
+            classify(
+                IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+                Scope::Global
+            ),
+            AddressScope::UniqueLocal
+        );
+        assert_eq!(
+            classify(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), Scope::Global),
+            AddressScope::Private
+        );
+    }
+
+    #[test]
+    fn link_local_addresses_only_reach_the_link() {
+        let link_local = (Ipv4Addr::new(169, 254, 3, 4), Scope::Link);
+        let gateway = Ipv4Addr::new(169, 254, 0, 1);
+        let only_link_local = address_gateway(&[link_local], &[gateway]);
+        assert_eq!(
+            only_link_local.connectivity_state(LinkState::Up),
+            ConnectivityState::LinkLocal
+        );
+        assert_eq!(only_link_local.path(2, LinkState::Up), None);
+        assert_eq!(
+            only_link_local.connectivity_state(LinkState::Down),
+            ConnectivityState::None
+        );
+    }
+
+    #[test]
+    fn widest_usable_scope_decides_the_connectivity() {
+        let link_local = (Ipv4Addr::new(169, 254, 3, 4), Scope::Link);
+        let global = (Ipv4Addr::new(198, 51, 100, 7), Scope::Global);
+        let gateway = Ipv4Addr::new(198, 51, 100, 1);
+
+        let mixed = address_gateway(&[link_local, global], &[]);
+        assert_eq!(
+            mixed.connectivity_state(LinkState::Up),
+            ConnectivityState::Network
+        );
+
+        let mut routed = address_gateway(&[link_local, global], &[gateway]);
+        assert_eq!(
+            routed.connectivity_state(LinkState::Up),
+            ConnectivityState::Internet
+        );
+        let path = routed.path(2, LinkState::Up).unwrap();
+        assert_eq!(
+            path.addresses,
+            vec![IpAddr::V4(link_local.0), IpAddr::V4(global.0)]
+        );
+
+        // a global address that isn't usable doesn't count
+        if let Some(properties) = routed.addresses.get_mut(&global.0) {
+            properties.state = AddressState::Tentative;
+        }
+        assert_eq!(
+            routed.connectivity_state(LinkState::Up),
+            ConnectivityState::LinkLocal
+        );
     }
 }
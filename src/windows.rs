@@ -2,16 +2,28 @@
 
 //! The windows implementation for this crate.
 
-use crate::{Connectivity, ConnectivityState};
+use crate::{
+    sender::Emitter,
+    state::{AddressInfo, Filter, Interfaces, LinkInfo, RouteGateway, Scope},
+    AddressState, Connectivity, Error, LinkState,
+};
 use core::{
-    cmp::max,
     ffi::c_void,
+    pin::Pin,
     ptr::{addr_of, addr_of_mut, null_mut},
+    time::Duration,
+};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    select_biased, Future, FutureExt, StreamExt,
 };
-use futures::Future;
 use log::{debug, warn};
-use std::{error::Error, sync::Mutex};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use std::{
+    collections::HashSet,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Instant,
+};
 use windows::Win32::{
     Foundation::HANDLE,
     NetworkManagement::{
@@ -23,17 +35,91 @@ use windows::Win32::{
             MIB_IPINTERFACE_ROW, MIB_NOTIFICATION_TYPE, MIB_UNICASTIPADDRESS_ROW,
             MIB_UNICASTIPADDRESS_TABLE,
         },
-        Ndis::IfOperStatusUp,
+        Ndis::{
+            IfOperStatusDormant, IfOperStatusLowerLayerDown, IfOperStatusTesting, IfOperStatusUp,
+            NET_IF_ADMIN_STATUS_UP,
+        },
+    },
+    Networking::WinSock::{
+        IpDadStateDeprecated, IpDadStateDuplicate, IpDadStatePreferred, ADDRESS_FAMILY, AF_INET,
+        AF_INET6, AF_UNSPEC, SOCKADDR_INET,
     },
-    Networking::WinSock::{ADDRESS_FAMILY, AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_INET},
 };
 
-/// Struct with named fields containing the sender channel and the current state
+/// Struct with named fields containing the sender channel
 struct SenderState {
     /// The transmit end of a channel to send notifications to
-    tx: Mutex<UnboundedSender<Connectivity>>,
-    /// The current connectivity
-    state: Mutex<Connectivity>,
+    tx: UnboundedSender<()>,
+}
+
+/// Registration of an ip interface change notification which cancels the notification on drop
+///
+/// The context the notification callback receives stays allocated until the notification is canceled.
+struct Notification {
+    /// The handle of the notification while it is registered
+    handle: Option<HANDLE>,
+    /// The context passed to the notification callback, which is leaked when the notification couldn't be canceled
+    context: Option<Pin<Box<SenderState>>>,
+}
+impl Notification {
+    /// Registers an ip interface change notification which sends to tx.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the registration failed.
+    fn new(tx: UnboundedSender<()>) -> Result<Self, Error> {
+        let context = Box::pin(SenderState { tx });
+        let family = AF_UNSPEC
+            .0
+            .try_into()
+            .map_err(|error| Error::Socket(io::Error::new(io::ErrorKind::InvalidInput, error)))?;
+        let mut handle = HANDLE::default();
+        // SAFETY:
+        // Invoking an unsafe windows api
+        // context is pinned and only freed after the notification is canceled
+        unsafe {
+            NotifyIpInterfaceChange(
+                family,
+                Some(connectivity_changed),
+                Some(addr_of!(*context).cast::<c_void>()),
+                false,
+                &mut handle,
+            )
+            .map_err(|error| Error::Socket(error.into()))?;
+        }
+        Ok(Self {
+            handle: Some(handle),
+            context: Some(context),
+        })
+    }
+
+    /// Cancels the notification.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the notification couldn't be canceled, in which case its context is leaked.
+    fn cancel(&mut self) -> Result<(), Error> {
+        if let Some(handle) = self.handle.take() {
+            debug!("canceling ip interface change notification");
+            // SAFETY:
+            // cleanup of handle for earlier unsafe windows api
+            if let Err(error) = unsafe { CancelMibChangeNotify2(handle) } {
+                // the callback can still run, so its context must stay allocated
+                if let Some(context) = self.context.take() {
+                    Box::leak(Pin::into_inner(context));
+                }
+                return Err(Error::Socket(error.into()));
+            }
+        }
+        Ok(())
+    }
+}
+impl Drop for Notification {
+    fn drop(&mut self) {
+        if let Err(error) = self.cancel() {
+            warn!("canceling ip interface change notification failed {error}");
+        }
+    }
 }
 
 /// Wrapper around windows MIB_*_TABLE* structures which calls `FreeMibTable` on drop
@@ -62,12 +148,12 @@ struct MibTableIter<'a, T> {
 macro_rules! create_mib_table_new {
     ($table:ty,$getter:expr) => {
         impl MibTable<$table> {
-            fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
+            fn new() -> Result<Self, Error> {
                 // SAFETY:
                 // getter is an unsafe windows api that should be dropped with `FreeMibTable`
                 unsafe {
                     let mut pointer = null_mut::<$table>();
-                    $getter(addr_of_mut!(pointer))?;
+                    $getter(addr_of_mut!(pointer)).map_err(|error| Error::Dump(Box::new(error)))?;
                     Ok(Self { pointer })
                 }
             }
@@ -75,12 +161,13 @@ macro_rules! create_mib_table_new {
     };
     ($table:ty,$getter:expr,$arg1:ty) => {
         impl MibTable<$table> {
-            fn new(a1: $arg1) -> Result<Self, Box<dyn Error + Send + Sync>> {
+            fn new(a1: $arg1) -> Result<Self, Error> {
                 // SAFETY:
                 // getter is an unsafe windows api that should be dropped with `FreeMibTable`
                 unsafe {
                     let mut pointer = null_mut::<$table>();
-                    $getter(a1, addr_of_mut!(pointer))?;
+                    $getter(a1, addr_of_mut!(pointer))
+                        .map_err(|error| Error::Dump(Box::new(error)))?;
                     Ok(Self { pointer })
                 }
             }
@@ -141,11 +228,115 @@ fn sockaddr_inet_check_ip_type(address: SOCKADDR_INET, ip_type: ADDRESS_FAMILY)
     ADDRESS_FAMILY(u32::from(unsafe { address.si_family })) == ip_type
 }
 
-/// Get the connectivity state from the system
-fn connectivity_from_system() -> Result<Connectivity, Box<dyn Error + Send + Sync>> {
+/// Convert a windows address structure to an [`IpAddr`]
+fn sockaddr_inet_to_ip_address(address: SOCKADDR_INET) -> Option<IpAddr> {
+    if sockaddr_inet_check_ip_type(address, AF_INET) {
+        // SAFETY:
+        // accessing union's ipv4 field which is checked by its identifier field
+        let ipv4_address = unsafe { address.Ipv4.sin_addr.S_un.S_addr };
+        Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(ipv4_address))))
+    } else if sockaddr_inet_check_ip_type(address, AF_INET6) {
+        // SAFETY:
+        // accessing union's ipv6 field which is checked by its identifier field
+        let ipv6_address = unsafe { address.Ipv6.sin6_addr.u.Byte };
+        Some(IpAddr::V6(Ipv6Addr::from(ipv6_address)))
+    } else {
+        None
+    }
+}
+
+/// Extract useful information from a [`MIB_IF_ROW2`].
+fn parse_link(interface: &MIB_IF_ROW2) -> LinkInfo {
+    let alias = &interface.Alias;
+    let length = alias
+        .iter()
+        .position(|&character| character == 0)
+        .unwrap_or(alias.len());
+    let name = alias.get(..length).map(String::from_utf16_lossy);
+    let mac = usize::try_from(interface.PhysicalAddressLength)
+        .ok()
+        .and_then(|mac_length| interface.PhysicalAddress.get(..mac_length))
+        .filter(|mac| !mac.is_empty())
+        .map(<[u8]>::to_vec);
+    LinkInfo {
+        index: interface.InterfaceIndex,
+        name,
+        loopback: interface.Type == IF_TYPE_SOFTWARE_LOOPBACK,
+        state: link_state(interface),
+        mac,
+        mtu: Some(interface.Mtu),
+        kind: None,
+        bridge_port: false,
+    }
+}
+
+/// Extract useful information from a [`MIB_UNICASTIPADDRESS_ROW`].
+fn parse_address(address: &MIB_UNICASTIPADDRESS_ROW) -> Option<AddressInfo> {
+    let ip_address = sockaddr_inet_to_ip_address(address.Address)?;
+    let dad_state = address.DadState;
+    let state = if dad_state == IpDadStatePreferred {
+        AddressState::Preferred
+    } else if dad_state == IpDadStateDeprecated {
+        AddressState::Deprecated
+    } else if dad_state == IpDadStateDuplicate {
+        AddressState::Duplicate
+    } else {
+        AddressState::Tentative
+    };
+    let scope = match ip_address {
+        IpAddr::V4(ipv4_address) if ipv4_address.is_loopback() => Scope::Host,
+        IpAddr::V4(ipv4_address) if ipv4_address.is_link_local() => Scope::Link,
+        IpAddr::V6(ipv6_address) if ipv6_address.is_loopback() => Scope::Host,
+        IpAddr::V6(ipv6_address) if ipv6_address.segments()[0] & 0xffc0 == 0xfe80 => Scope::Link,
+        IpAddr::V4(_) | IpAddr::V6(_) => Scope::Global,
+    };
+    let now = Instant::now();
+    Some(AddressInfo {
+        index: address.InterfaceIndex,
+        address: ip_address,
+        state,
+        scope,
+        preferred_until: expires(now, address.PreferredLifetime),
+        valid_until: expires(now, address.ValidLifetime),
+        permanent: address.ValidLifetime == u32::MAX,
+    })
+}
+
+/// Get the instant a lifetime in seconds starting at now expires, which is never for a lifetime of `u32::MAX`
+fn expires(now: Instant, lifetime: u32) -> Option<Instant> {
+    (lifetime != u32::MAX)
+        .then(|| now.checked_add(Duration::from_secs(u64::from(lifetime))))
+        .flatten()
+}
+
+/// Derive the [`LinkState`] of a [`MIB_IF_ROW2`] from its administrative and operational status
+fn link_state(interface: &MIB_IF_ROW2) -> LinkState {
+    if interface.AdminStatus != NET_IF_ADMIN_STATUS_UP {
+        return LinkState::Down;
+    }
+    let operational = interface.OperStatus;
+    if operational == IfOperStatusUp {
+        LinkState::Up
+    } else if operational == IfOperStatusDormant {
+        LinkState::Dormant
+    } else if operational == IfOperStatusTesting {
+        LinkState::Testing
+    } else if operational == IfOperStatusLowerLayerDown {
+        LinkState::LowerLayerDown
+    } else {
+        LinkState::Down
+    }
+}
+
+/// Get the state that passes filter from the system
+fn state_from_system(filter: &Filter) -> Result<Interfaces, Error> {
     let interfaces = MibTable::<MIB_IF_TABLE2>::new()?;
-    let addresses = MibTable::<MIB_UNICASTIPADDRESS_TABLE>::new(AF_UNSPEC.0.try_into()?)?;
-    let routes = MibTable::<MIB_IPFORWARD_TABLE2>::new(AF_UNSPEC.0.try_into()?)?;
+    let family = AF_UNSPEC
+        .0
+        .try_into()
+        .map_err(|error| Error::Dump(Box::new(error)))?;
+    let addresses = MibTable::<MIB_UNICASTIPADDRESS_TABLE>::new(family)?;
+    let routes = MibTable::<MIB_IPFORWARD_TABLE2>::new(family)?;
 
     let default_routes = routes.into_iter().filter(|route| {
         let mut prefix_compare = SOCKADDR_INET::default();
@@ -156,84 +347,58 @@ fn connectivity_from_system() -> Result<Connectivity, Box<dyn Error + Send + Syn
             && route.DestinationPrefix.Prefix == prefix_compare
     });
 
-    let connectivity = interfaces
+    let mut state = Interfaces::new(filter.clone());
+    let mut indices = HashSet::new();
+    for interface in interfaces.into_iter().filter(|interface| {
+        #[allow(clippy::used_underscore_binding)]
+        return interface.InterfaceAndOperStatusFlags._bitfield & 1 == 1;
+    }) {
+        indices.insert(interface.InterfaceIndex);
+        state.add_link(parse_link(interface));
+    }
+    for address in addresses
         .into_iter()
-        .filter(|interface| {
-            #[allow(clippy::used_underscore_binding)]
-            return interface.InterfaceAndOperStatusFlags._bitfield & 1 == 1
-                && interface.Type != IF_TYPE_SOFTWARE_LOOPBACK
-                && interface.OperStatus == IfOperStatusUp;
-        })
-        .map(|interface| {
-            let interface_addresses = addresses
-                .into_iter()
-                .filter(|address| address.InterfaceIndex == interface.InterfaceIndex);
-            let mut ipv4_interface_addresses = interface_addresses
-                .clone()
-                .filter(|address| sockaddr_inet_check_ip_type(address.Address, AF_INET));
-            let mut ipv6_interface_addresses = interface_addresses
-                .clone()
-                .filter(|address| sockaddr_inet_check_ip_type(address.Address, AF_INET6));
-            let interface_default_routes = default_routes
-                .clone()
-                .filter(|route| route.InterfaceIndex == interface.InterfaceIndex);
-            let mut ipv4_interface_default_routes = interface_default_routes
-                .clone()
-                .filter(|route| sockaddr_inet_check_ip_type(route.NextHop, AF_INET));
-            let mut ipv6_interface_default_routes = interface_default_routes
-                .clone()
-                .filter(|route| sockaddr_inet_check_ip_type(route.NextHop, AF_INET6));
-
-            let ipv4 = match (
-                ipv4_interface_addresses.next(),
-                ipv4_interface_default_routes.next(),
-            ) {
-                (None, _) => ConnectivityState::None,
-                (Some(_), None) => ConnectivityState::Network,
-                (Some(_), Some(_)) => ConnectivityState::Internet,
-            };
-            let ipv6 = match (
-                ipv6_interface_addresses.next(),
-                ipv6_interface_default_routes.next(),
-            ) {
-                (None, _) => ConnectivityState::None,
-                (Some(_), None) => ConnectivityState::Network,
-                (Some(_), Some(_)) => ConnectivityState::Internet,
-            };
+        .filter(|address| indices.contains(&address.InterfaceIndex))
+    {
+        if let Some(address_info) = parse_address(address) {
+            state.add_address(&address_info);
+        }
+    }
+    for route in default_routes.filter(|route| indices.contains(&route.InterfaceIndex)) {
+        // the next hop of a default route without a gateway is the unspecified address
+        let gateway = match sockaddr_inet_to_ip_address(route.NextHop) {
+            Some(IpAddr::V4(ipv4_address)) => {
+                RouteGateway::V4(Some(ipv4_address).filter(|address| !address.is_unspecified()))
+            }
+            Some(IpAddr::V6(ipv6_address)) => {
+                RouteGateway::V6(Some(ipv6_address).filter(|address| !address.is_unspecified()))
+            }
+            None => continue,
+        };
+        state.add_default_route((route.InterfaceIndex, gateway, route.Metric));
+    }
 
-            Connectivity { ipv4, ipv6 }
-        })
-        .reduce(|a, b| Connectivity {
-            ipv4: max(a.ipv4, b.ipv4),
-            ipv6: max(a.ipv6, b.ipv6),
-        });
+    Ok(state)
+}
 
-    Ok(connectivity.unwrap_or(Connectivity {
-        ipv4: ConnectivityState::None,
-        ipv6: ConnectivityState::None,
-    }))
+/// Gets the connectivity for the state that passes filter from the system.
+///
+/// # Errors
+///
+/// This function will return an error if the state couldn't be retrieved.
+pub fn current(filter: &Filter) -> Result<Connectivity, Error> {
+    Ok(state_from_system(filter)?.connectivity())
 }
 
 /// the handler function for `connectivity_changed` that returns a result which writes better to read code.
-unsafe fn handle_connectivity_changed(
-    caller_context: *const c_void,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let sender_state_pointer = caller_context.cast::<SenderState>().cast_mut();
-    if let Some(sender_state) = sender_state_pointer.as_mut() {
-        let mut state = sender_state
-            .state
-            .lock()
-            .map_err(|error| format!("failed to lock state: {error}"))?;
-        let new_connectivity = connectivity_from_system()?;
-        if *state != new_connectivity {
-            debug!("emitting updated connectivity {new_connectivity:?}");
-            sender_state
-                .tx
-                .lock()
-                .map_err(|error| format!("failed to lock sender: {error}"))?
-                .send(new_connectivity)?;
-            *state = new_connectivity;
-        }
+unsafe fn handle_connectivity_changed(caller_context: *const c_void) -> Result<(), Error> {
+    let sender_state_pointer = caller_context.cast::<SenderState>();
+    if let Some(sender_state) = sender_state_pointer.as_ref() {
+        debug!("notifying driver");
+        sender_state
+            .tx
+            .unbounded_send(())
+            .map_err(|_closed| Error::Closed)?;
     }
     Ok(())
 }
@@ -256,75 +421,61 @@ unsafe extern "system" fn connectivity_changed(
     }
 }
 
-/// Subscribes some functions to the windows api and sends connectivity updates.
+/// Subscribes some functions to the windows api and sends updates through emitter for the state that passes filter.
 ///
 /// # Returns
 ///
-/// The return value consists of a future that must be awaited and the receive end of a channel through which connectivity updates are received.
+/// The return value is a future that must be awaited.
 ///
 /// # Notes
 ///
-/// When the receive end of the channel is dropped, the future will run to completion.
+/// When the receive end of the sender of emitter is dropped, the future will run to completion.
 ///
 /// # Errors
 ///
 /// This function will return an error if the subscription failed.
 /// The returned future can fail when a cleanup of the subscription failed.
-pub fn new() -> Result<
-    (
-        impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>>,
-        UnboundedReceiver<Connectivity>,
-    ),
-    Box<dyn Error + Send + Sync>,
-> {
-    let (tx, rx) = unbounded_channel();
-    let connectivity = connectivity_from_system()?;
-    let sender_state = Box::pin(SenderState {
-        tx: Mutex::new(tx),
-        state: Mutex::new(connectivity),
-    });
-
-    {
-        debug!("emitting initial connectivity {:?}", connectivity);
-        sender_state
-            .tx
-            .lock()
-            .map_err(|error| error.to_string())?
-            .send(connectivity)?;
-    }
+pub fn new(
+    mut emitter: Emitter,
+    filter: Filter,
+) -> Result<impl Future<Output = Result<(), Error>>, Error> {
+    debug!("emitting initial state");
+    emitter.update(&state_from_system(&filter)?, None)?;
+    emitter.flush()?;
 
+    let (tx, mut rx) = unbounded();
     debug!("creating ip interface change notification");
-    let mut handle = HANDLE::default();
-    // SAFETY:
-    // Invoking an unsafe windows api
-    // sender_state must be stationary in memory
-    // handle must be cleaned up when there is no more interest in the notification
-    unsafe {
-        NotifyIpInterfaceChange(
-            AF_UNSPEC.0.try_into()?,
-            Some(connectivity_changed),
-            Some(addr_of!(*sender_state).cast::<c_void>()),
-            false,
-            &mut handle,
-        )?;
-    }
+    let mut notification = Notification::new(tx)?;
 
     let driver = async move {
-        let locked_tx = sender_state
-            .tx
-            .lock()
-            .map_err(|error| error.to_string())?
-            .clone();
-        debug!("waiting on sender closed");
-        locked_tx.closed().await;
-        debug!("canceling ip interface change notification");
-        // SAFETY:
-        // cleanup of handle for earlier unsafe windows api
-        unsafe {
-            CancelMibChangeNotify2(handle)?;
+        debug!("waiting on notifications or sender closed");
+        let mut closed = emitter.closed();
+        loop {
+            let next_notification = select_biased! {
+                _ = closed => {
+                    debug!("transmit channel closed");
+                    break;
+                },
+                idled = emitter.idle().fuse() => {
+                    idled?;
+                    continue;
+                },
+                next_notification = rx.next() => next_notification,
+            };
+            if next_notification.is_none() {
+                debug!("no more notifications");
+                break;
+            }
+            match state_from_system(&filter) {
+                Ok(state) => {
+                    debug!("emitting updated state");
+                    emitter.update(&state, None)?;
+                }
+                Err(error) => warn!("state_from_system failed {error}"),
+            }
         }
-        Ok(())
+        notification.cancel()
     };
 
-    Ok((driver, rx))
+    Ok(driver)
 }